@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use goggles_lib::upload::{prepare_upload, UploadFormat, UploadOptions};
+use image::{Rgba, RgbaImage};
+
+/// A retina-sized screenshot with enough detail to make encoding work.
+fn screenshot(path: &Path) {
+    RgbaImage::from_fn(2880, 1800, |x, y| {
+        Rgba([(x % 251) as u8, (y % 241) as u8, ((x ^ y) % 256) as u8, 255])
+    })
+    .save(path)
+    .unwrap();
+}
+
+fn bench_prepare_upload(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("screenshot.png");
+    screenshot(&path);
+
+    for format in [UploadFormat::Jpeg, UploadFormat::Webp] {
+        let options = UploadOptions {
+            max_dimension: 1024,
+            max_bytes: 512 * 1024,
+            format,
+            redact_regions: vec![],
+        };
+        c.bench_function(&format!("prepare_upload {:?}", format), |b| {
+            b.iter(|| prepare_upload(&path, &options).unwrap())
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_prepare_upload
+}
+criterion_main!(benches);
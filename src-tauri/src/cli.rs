@@ -0,0 +1,605 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::{Args, Parser, Subcommand};
+use log::{error, info, warn};
+use serde::Serialize;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::watcher::{
+    self,
+    config::{GogglesConfig, WatchedFolder},
+    daemon::DaemonOptions,
+    image::SSManager,
+    journal::RenameJournal,
+    layers::load_layered,
+    naming::NamingContext,
+};
+
+/// Extensions picked up when a directory or glob is expanded.
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// Goggles renames new screenshots and images with AI-suggested names.
+/// Without a subcommand the desktop app is started, if built with `gui`.
+#[derive(Debug, Parser)]
+#[command(name = "goggles", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Log filter, e.g. `info`, `debug` or `goggles_lib=trace`. Overrides
+    /// `RUST_LOG`, which defaults to `info`
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run only the watcher daemon, without the GUI
+    Watch(WatchArgs),
+    /// Rename files, globs or folders once and exit
+    Rename(RenameArgs),
+    /// Print the effective config, merged from every config source
+    Config(ConfigArgs),
+    /// Move a renamed file back to its original name
+    Undo(UndoArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Folder to watch instead of the configured ones, can be repeated
+    #[arg(long = "dir", value_name = "DIR")]
+    pub dirs: Vec<PathBuf>,
+
+    /// Also watch subfolders of every `--dir`
+    #[arg(long, requires = "dirs")]
+    pub recursive: bool,
+
+    /// Name files in every `--dir` locally, without uploading them
+    #[arg(long, requires = "dirs")]
+    pub never_upload: bool,
+
+    /// Use this config file instead of the default `config.json`
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Log the renames that would happen without moving any file
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RenameArgs {
+    /// Files, glob patterns or folders to rename
+    #[arg(required = true, value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
+
+    /// Descend into subfolders of every folder given
+    #[arg(long, short)]
+    pub recursive: bool,
+
+    /// Use this config file instead of the default `config.json`
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Print the new names without moving any file
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print the results as JSON instead of one line per file
+    #[arg(long)]
+    pub json: bool,
+
+    /// Number of files named at the same time
+    #[arg(long, short = 'j', default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: u16,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    /// Print which source set each key instead of the values
+    #[arg(long)]
+    pub sources: bool,
+
+    /// Use this config file instead of the default `config.json`
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct UndoArgs {
+    /// Journal id of the rename to undo, the most recent one if omitted
+    #[arg(long)]
+    pub id: Option<u64>,
+}
+
+/// Result of renaming one file, as printed by `goggles rename --json`.
+#[derive(Debug, Serialize)]
+pub struct RenameOutcome {
+    pub path: PathBuf,
+    pub new_path: Option<PathBuf>,
+    pub error: Option<String>,
+    pub dry_run: bool,
+}
+
+impl WatchArgs {
+    fn daemon_options(&self) -> DaemonOptions {
+        let folders = (!self.dirs.is_empty()).then(|| {
+            self.dirs
+                .iter()
+                .map(|dir| WatchedFolder {
+                    path: dir.clone(),
+                    recursive: self.recursive,
+                    filters: vec![],
+                    template: None,
+                    enabled: true,
+                    screenshots_only: false,
+                    never_upload: self.never_upload,
+                })
+                .collect()
+        });
+
+        DaemonOptions {
+            folders,
+            dry_run: self.dry_run,
+        }
+    }
+}
+
+/// Runs a headless subcommand.
+pub async fn run(command: Command) {
+    match command {
+        Command::Watch(args) => watch(args).await,
+        Command::Rename(args) => {
+            if !rename(args).await {
+                std::process::exit(1);
+            }
+        }
+        Command::Config(args) => {
+            if !show_config(args) {
+                std::process::exit(1);
+            }
+        }
+        Command::Undo(args) => {
+            if !undo(args) {
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+async fn watch(args: WatchArgs) {
+    if let Some(config) = &args.config {
+        GogglesConfig::set_config_path(config.clone());
+    }
+
+    // Refuse to start a second instance, both would rename the same files
+    if let Err(e) = watcher::pid::acquire() {
+        if e.downcast_ref::<watcher::pid::AlreadyRunning>().is_some() {
+            error!("{}", e);
+        } else {
+            // without the lock nothing stops a second watcher from starting
+            error!("Failed to lock the PID file, not starting: {:?}", e);
+        }
+        std::process::exit(1);
+    }
+
+    if args.dry_run {
+        info!("Dry run, files will not be renamed");
+    }
+    watcher::daemon::run_with_options(args.daemon_options()).await;
+
+    watcher::pid::release();
+}
+
+/// Prints the effective config or, with `--sources`, the layer each key
+/// came from. Returns false when the config can't be loaded.
+fn show_config(args: ConfigArgs) -> bool {
+    if let Some(config) = &args.config {
+        GogglesConfig::set_config_path(config.clone());
+    }
+
+    let layered = match load_layered() {
+        Ok(layered) => layered,
+        Err(e) => {
+            error!("Failed to load config: {}", e);
+            return false;
+        }
+    };
+    let json = if args.sources {
+        serde_json::to_string_pretty(&layered.sources)
+    } else {
+        serde_json::to_string_pretty(&layered.config)
+    };
+    match json {
+        Ok(json) => {
+            println!("{}", json);
+            true
+        }
+        Err(e) => {
+            error!("Failed to serialize config: {}", e);
+            false
+        }
+    }
+}
+
+/// Undoes one journaled rename, returns false when it couldn't be undone.
+fn undo(args: UndoArgs) -> bool {
+    let undone = RenameJournal::open().and_then(|journal| match args.id {
+        Some(id) => journal.undo_rename(id),
+        None => journal.undo_last(),
+    });
+    match undone {
+        Ok(entry) => {
+            println!(
+                "{} -> {}",
+                entry.new_path.display(),
+                entry.original_path.display()
+            );
+            true
+        }
+        Err(e) => {
+            error!("Failed to undo rename: {}", e);
+            false
+        }
+    }
+}
+
+/// Expands the files, globs and folders given on the command line into
+/// absolute paths, the way the daemon and the journal see them. Explicit
+/// files are kept as is, expanded ones must look like images.
+fn collect_files(inputs: &[PathBuf], recursive: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_file() {
+            files.push(input.clone());
+        } else if input.is_dir() {
+            collect_dir(input, recursive, &mut files);
+        } else {
+            let pattern = input.to_string_lossy();
+            // like folders, hidden files only match when named explicitly
+            let options = glob::MatchOptions {
+                require_literal_leading_dot: true,
+                ..Default::default()
+            };
+            match glob::glob_with(&pattern, options) {
+                Ok(paths) => {
+                    let before = files.len();
+                    for path in paths.flatten() {
+                        if path.is_dir() {
+                            collect_dir(&path, recursive, &mut files);
+                        } else if is_image(&path) {
+                            files.push(path);
+                        }
+                    }
+                    if files.len() == before {
+                        warn!("No files match {}", pattern);
+                    }
+                }
+                Err(e) => warn!("Invalid path or pattern {}: {}", pattern, e),
+            }
+        }
+    }
+
+    let mut files: Vec<PathBuf> = files
+        .into_iter()
+        .filter_map(|path| match fs::canonicalize(&path) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                warn!("Skipping {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn collect_dir(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            if recursive {
+                collect_dir(&path, recursive, files);
+            }
+        } else if is_image(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Renames every file in `args`, returns false if any of them failed.
+async fn rename(args: RenameArgs) -> bool {
+    if let Some(config) = &args.config {
+        GogglesConfig::set_config_path(config.clone());
+    }
+
+    let (manager, context) = match build_rename_manager(args.dry_run) {
+        Ok(built) => built,
+        Err(e) => {
+            error!("{}", e);
+            return false;
+        }
+    };
+
+    let files = collect_files(&args.paths, args.recursive);
+    info!("Renaming {} files", files.len());
+
+    let outcomes = rename_files(
+        &manager,
+        &context,
+        files,
+        args.concurrency as usize,
+        !args.json,
+    )
+    .await;
+    if args.json {
+        match serde_json::to_string_pretty(&outcomes) {
+            Ok(json) => println!("{}", json),
+            Err(e) => error!("Failed to serialize results: {}", e),
+        }
+    }
+
+    outcomes.iter().all(|outcome| outcome.error.is_none())
+}
+
+/// Renames `files` with at most `concurrency` at a time, returning the
+/// outcomes in the order of `files`. With `print` set, each one is printed
+/// as it finishes.
+async fn rename_files(
+    manager: &SSManager,
+    context: &NamingContext,
+    files: Vec<PathBuf>,
+    concurrency: usize,
+    print: bool,
+) -> Vec<RenameOutcome> {
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = JoinSet::new();
+    let mut spawned = HashMap::new();
+    for (index, path) in files.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        let manager = manager.clone();
+        let context = context.clone();
+        let task_path = path.clone();
+        let handle = tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            if manager.journal().is_renamed_file(&task_path) {
+                Err(anyhow::anyhow!("Already renamed by Goggles"))
+            } else {
+                manager.process_random_image(&context, &task_path).await
+            }
+        });
+        spawned.insert(handle.id(), (index, path));
+    }
+
+    let mut outcomes = Vec::new();
+    while let Some(joined) = tasks.join_next_with_id().await {
+        let (id, result) = match joined {
+            Ok((id, result)) => (id, result),
+            // a panicking rename still gets an outcome, and a failed exit
+            Err(e) => (e.id(), Err(anyhow::anyhow!("Rename task failed: {}", e))),
+        };
+        let Some((index, path)) = spawned.remove(&id) else {
+            continue;
+        };
+        let (new_path, error) = match result {
+            Ok(new_path) => (Some(new_path), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        if print {
+            match (&new_path, &error) {
+                (Some(new_path), _) => println!("{} -> {}", path.display(), new_path.display()),
+                (_, Some(error)) => eprintln!("{}: {}", path.display(), error),
+                _ => {}
+            }
+        }
+        outcomes.push((
+            index,
+            RenameOutcome {
+                path,
+                new_path,
+                error,
+                dry_run: manager.is_dry_run(),
+            },
+        ));
+    }
+
+    outcomes.sort_by_key(|(index, _)| *index);
+    outcomes.into_iter().map(|(_, o)| o).collect()
+}
+
+fn build_rename_manager(dry_run: bool) -> Result<(SSManager, NamingContext), anyhow::Error> {
+    let config = GogglesConfig::load_effective()
+        .map_err(|e| anyhow::anyhow!("Failed to load config: {}", e))?;
+    let journal = RenameJournal::open()
+        .map_err(|e| anyhow::anyhow!("Failed to open rename journal: {}", e))?;
+
+    let manager = SSManager::from_config(&config, journal)?.with_dry_run(dry_run);
+    Ok((manager, NamingContext::new(config.address)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::watcher::local::LocalBackend;
+    use crate::watcher::naming::{NameSuggestion, NamingBackend};
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+    }
+
+    /// A folder with images at two levels, a hidden one and a text file.
+    fn tree() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for name in ["a.png", "b.JPG", ".hidden.png", "notes.txt", "sub/c.webp"] {
+            touch(&root.join(name));
+        }
+        (dir, root)
+    }
+
+    #[test]
+    fn expands_folders_with_and_without_recursion() {
+        let (_dir, root) = tree();
+
+        assert_eq!(
+            collect_files(std::slice::from_ref(&root), false),
+            vec![root.join("a.png"), root.join("b.JPG")]
+        );
+        assert_eq!(
+            collect_files(std::slice::from_ref(&root), true),
+            vec![
+                root.join("a.png"),
+                root.join("b.JPG"),
+                root.join("sub/c.webp")
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_hidden_files_and_folders() {
+        let (_dir, root) = tree();
+        touch(&root.join(".cache/d.png"));
+
+        let files = collect_files(std::slice::from_ref(&root), true);
+        assert!(!files.contains(&root.join(".hidden.png")));
+        assert!(!files.contains(&root.join(".cache/d.png")));
+    }
+
+    #[test]
+    fn expands_globs_once_per_file() {
+        let (_dir, root) = tree();
+        let pattern = root.join("*.png");
+
+        let files = collect_files(&[pattern.clone(), root.join("a.png"), pattern], false);
+        assert_eq!(files, vec![root.join("a.png")]);
+        // a pattern matching nothing is only a warning
+        assert!(collect_files(&[root.join("*.gif")], false).is_empty());
+    }
+
+    #[test]
+    fn keeps_explicit_files_that_are_not_images() {
+        let (_dir, root) = tree();
+
+        let files = collect_files(&[root.join("notes.txt"), root.join("sub")], false);
+        assert_eq!(files, vec![root.join("notes.txt"), root.join("sub/c.webp")]);
+    }
+
+    #[test]
+    fn returns_absolute_paths() {
+        let (_dir, root) = tree();
+        let indirect = root.join("sub/../a.png");
+
+        assert_eq!(collect_files(&[indirect], false), vec![root.join("a.png")]);
+    }
+
+    #[tokio::test]
+    async fn reports_dry_run_outcomes_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let journal = RenameJournal::new(root.join("journal.jsonl"));
+        let manager = SSManager::new(Arc::new(LocalBackend::new()), journal).with_dry_run(true);
+
+        let mut files = Vec::new();
+        for width in [24, 8, 16] {
+            let path = root.join(format!("image-{}.png", width));
+            image::RgbImage::new(width, 8).save(&path).unwrap();
+            files.push(path);
+        }
+        let missing = root.join("missing.png");
+        files.insert(1, missing.clone());
+
+        let outcomes =
+            rename_files(&manager, &NamingContext::default(), files.clone(), 2, false).await;
+
+        let json = serde_json::to_value(&outcomes).unwrap();
+        let json = json.as_array().unwrap();
+        assert_eq!(json.len(), files.len());
+        for (outcome, path) in json.iter().zip(&files) {
+            let object = outcome.as_object().unwrap();
+            let mut keys: Vec<_> = object.keys().map(String::as_str).collect();
+            keys.sort();
+            assert_eq!(keys, ["dry_run", "error", "new_path", "path"]);
+            assert_eq!(outcome["path"], path.to_str().unwrap());
+            assert_eq!(outcome["dry_run"], true);
+            if path == &missing {
+                assert!(outcome["new_path"].is_null());
+                assert!(outcome["error"].is_string());
+            } else {
+                assert!(outcome["new_path"].is_string());
+                assert!(outcome["error"].is_null());
+                // nothing was moved
+                assert!(path.exists());
+            }
+        }
+    }
+
+    /// Panics while naming any file.
+    struct Panics;
+
+    #[async_trait::async_trait]
+    impl NamingBackend for Panics {
+        fn id(&self) -> &str {
+            "panics"
+        }
+
+        async fn suggest_name(
+            &self,
+            _path: &Path,
+            _context: &NamingContext,
+        ) -> Result<NameSuggestion, anyhow::Error> {
+            panic!("backend bug");
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_renames_that_panicked_as_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let journal = RenameJournal::new(root.join("journal.jsonl"));
+        let manager = SSManager::new(Arc::new(Panics), journal);
+        let path = root.join("image.png");
+        image::RgbImage::new(8, 8).save(&path).unwrap();
+
+        let outcomes = rename_files(
+            &manager,
+            &NamingContext::default(),
+            vec![path.clone()],
+            1,
+            false,
+        )
+        .await;
+
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].path, path);
+        assert_eq!(outcomes[0].new_path, None);
+        assert!(outcomes[0].error.as_ref().unwrap().contains("panicked"));
+        assert!(path.exists());
+    }
+}
@@ -0,0 +1,385 @@
+#[cfg(not(target_os = "linux"))]
+use tauri_plugin_positioner::{Position, WindowExt};
+use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial, NSVisualEffectState};
+
+use crate::watcher;
+use crate::watcher::error::GogglesError;
+
+use log::{error, info};
+use std::collections::BTreeMap;
+use tauri::{
+    menu::{Menu, MenuEvent, MenuItem},
+    tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent},
+    AppHandle, Manager, WebviewUrl, WebviewWindowBuilder,
+};
+
+// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
+}
+
+#[tauri::command]
+async fn close_window(window: tauri::Window) -> Result<(), String> {
+    window.close().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn minimize_window(window: tauri::Window) -> Result<(), String> {
+    window.minimize().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn maximize_window(window: tauri::Window) -> Result<(), String> {
+    let is_maximized = window.is_maximized().map_err(|e| e.to_string())?;
+    if is_maximized {
+        window.unmaximize().map_err(|e| e.to_string())
+    } else {
+        window.maximize().map_err(|e| e.to_string())
+    }
+}
+
+#[tauri::command]
+async fn update_config_address(address: String) -> Result<(), GogglesError> {
+    let mut config = watcher::config::GogglesConfig::load().map_err(GogglesError::config)?;
+
+    config
+        .update_address(address)
+        .map_err(GogglesError::config)?;
+
+    info!("Config address updated successfully");
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_config_address() -> Result<String, GogglesError> {
+    let config = watcher::config::GogglesConfig::load_effective().map_err(GogglesError::config)?;
+
+    Ok(config.address)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ServerSettings {
+    server_url: String,
+    timeout_secs: Option<u64>,
+    auth_header: Option<String>,
+}
+
+#[tauri::command]
+async fn update_config_server(settings: ServerSettings) -> Result<(), GogglesError> {
+    let mut config = watcher::config::GogglesConfig::load().map_err(GogglesError::config)?;
+
+    config
+        .update_server(
+            settings.server_url,
+            settings.timeout_secs,
+            settings.auth_header,
+        )
+        .map_err(GogglesError::config)?;
+
+    info!("Config server updated successfully");
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_config_server() -> Result<ServerSettings, GogglesError> {
+    let config = watcher::config::GogglesConfig::load_effective().map_err(GogglesError::config)?;
+
+    Ok(ServerSettings {
+        server_url: config.server_url,
+        timeout_secs: config.server_timeout_secs,
+        auth_header: config.server_auth_header,
+    })
+}
+
+#[tauri::command]
+async fn get_config_sources() -> Result<BTreeMap<String, watcher::layers::ConfigLayer>, GogglesError>
+{
+    let layered = watcher::layers::load_layered().map_err(GogglesError::config)?;
+
+    Ok(layered.sources)
+}
+
+#[tauri::command]
+async fn get_finder_selection() -> Result<Vec<String>, String> {
+    match watcher::macos::get_finder_selection() {
+        Some(paths) => Ok(paths),
+        None => Ok(vec![]),
+    }
+}
+
+/// A manager for the current config, reusing the cached backend.
+fn rename_manager(
+    backends: &watcher::naming::BackendCache,
+    config: &watcher::config::GogglesConfig,
+) -> Result<watcher::image::SSManager, GogglesError> {
+    let journal = watcher::journal::RenameJournal::open()?;
+    let backend = backends.get(config)?;
+    Ok(watcher::image::SSManager::from_config_with_backend(
+        config, backend, journal,
+    )?)
+}
+
+#[tauri::command]
+async fn process_image_with_ai(
+    backends: tauri::State<'_, watcher::naming::BackendCache>,
+    file_path: String,
+) -> Result<String, GogglesError> {
+    let config = watcher::config::GogglesConfig::load_effective().map_err(GogglesError::config)?;
+
+    let ss_manager = rename_manager(&backends, &config)?;
+
+    let path = std::path::PathBuf::from(&file_path);
+    let context = watcher::naming::NamingContext::new(config.address);
+
+    ss_manager.process_random_image(&context, &path).await?;
+    Ok("Image processed successfully".to_string())
+}
+
+#[tauri::command]
+async fn preview_rename(
+    backends: tauri::State<'_, watcher::naming::BackendCache>,
+    file_path: String,
+) -> Result<watcher::plan::RenamePlan, GogglesError> {
+    let config = watcher::config::GogglesConfig::load_effective().map_err(GogglesError::config)?;
+
+    let ss_manager = rename_manager(&backends, &config)?;
+
+    let path = std::path::PathBuf::from(&file_path);
+    let context = watcher::naming::NamingContext::new(config.address);
+
+    ss_manager
+        .plan_rename(&context, &path)
+        .await
+        .map_err(GogglesError::from)
+}
+
+/// Accepts a plan from `preview_rename`, `name` replaces the suggested name
+/// when the user edited it. Rejecting a plan needs no call.
+#[tauri::command]
+async fn apply_rename(
+    backends: tauri::State<'_, watcher::naming::BackendCache>,
+    plan: watcher::plan::RenamePlan,
+    name: Option<String>,
+) -> Result<String, GogglesError> {
+    let config = watcher::config::GogglesConfig::load_effective().map_err(GogglesError::config)?;
+
+    let ss_manager = rename_manager(&backends, &config)?;
+
+    let context = watcher::naming::NamingContext::new(config.address);
+
+    ss_manager
+        .apply_plan(&context, &plan, name.as_deref())
+        .await
+        .map(|new_path| new_path.display().to_string())
+        .map_err(GogglesError::from)
+}
+
+#[tauri::command]
+fn get_job_statuses() -> Vec<watcher::queue::JobInfo> {
+    watcher::queue::job_statuses()
+}
+
+#[tauri::command]
+async fn update_name_template(template: String) -> Result<(), GogglesError> {
+    let mut config = watcher::config::GogglesConfig::load().map_err(GogglesError::config)?;
+
+    config
+        .update_name_template(template)
+        .map_err(GogglesError::config)?;
+
+    info!("Name template updated successfully");
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_name_template() -> Result<String, GogglesError> {
+    let config = watcher::config::GogglesConfig::load_effective().map_err(GogglesError::config)?;
+
+    Ok(config.name_template)
+}
+
+#[tauri::command]
+async fn preview_name_template(
+    template: String,
+    file_path: String,
+) -> Result<String, GogglesError> {
+    let path = std::path::PathBuf::from(&file_path);
+    watcher::template::preview_template(&template, &path)
+        .map_err(|e| GogglesError::Config(format!("Invalid name template: {}", e)))
+}
+
+#[tauri::command]
+async fn get_rename_history() -> Result<Vec<watcher::journal::JournalEntry>, GogglesError> {
+    watcher::journal::RenameJournal::open()
+        .and_then(|journal| journal.entries())
+        .map_err(GogglesError::from)
+}
+
+#[tauri::command]
+async fn undo_rename(id: u64) -> Result<watcher::journal::JournalEntry, GogglesError> {
+    watcher::journal::RenameJournal::open()
+        .and_then(|journal| journal.undo_rename(id))
+        .map_err(GogglesError::from)
+}
+
+#[tauri::command]
+async fn undo_last_rename() -> Result<watcher::journal::JournalEntry, GogglesError> {
+    watcher::journal::RenameJournal::open()
+        .and_then(|journal| journal.undo_last())
+        .map_err(GogglesError::from)
+}
+
+pub fn webview_window_builder(
+    app: &AppHandle,
+    window_name: &str,
+    url: &str,
+    width: f64,
+    height: f64,
+) {
+    if let Some(window) = app.get_webview_window(window_name) {
+        let _ = window.show();
+        let _ = window.set_focus();
+        return;
+    }
+
+    let window =
+        WebviewWindowBuilder::new(app, window_name, WebviewUrl::External(url.parse().unwrap()))
+            .title("Goggles App")
+            .inner_size(width, height)
+            .decorations(false)
+            .build()
+            .expect("Failed to create window");
+
+    // Position the window
+    #[cfg(not(target_os = "linux"))]
+    let _ = window.as_ref().window().move_window(Position::TopRight);
+}
+
+fn menu_event_handler(_app: &AppHandle, event: MenuEvent) {
+    match event.id.as_ref() {
+        "info" => {
+            // create new window with webview of /info
+            webview_window_builder(_app, "info", "http://localhost:1420/info", 600.0, 480.0);
+        }
+        "undo" => {
+            match watcher::journal::RenameJournal::open().and_then(|journal| journal.undo_last()) {
+                Ok(entry) => info!("Restored {:?} to {:?}", entry.new_path, entry.original_path),
+                Err(e) => error!("Failed to undo rename: {}", e),
+            }
+        }
+        "quit" => {
+            watcher::pid::release();
+            std::process::exit(0);
+        }
+        _ => {
+            println!("Other menu item clicked: {:?}", event);
+        }
+    }
+}
+
+fn tray_icon_event_handler(_tray: &TrayIcon, event: TrayIconEvent) {
+    match event {
+        TrayIconEvent::Click {
+            button: MouseButton::Left,
+            button_state: MouseButtonState::Up,
+            ..
+        } => {
+            println!("Tray icon left clicked");
+        }
+        TrayIconEvent::Click {
+            button: MouseButton::Right,
+            button_state: MouseButtonState::Up,
+            ..
+        } => {
+            println!("Tray icon right clicked");
+        }
+        TrayIconEvent::DoubleClick {
+            button: MouseButton::Left,
+            ..
+        } => {
+            println!("Tray icon double clicked");
+        }
+        _ => {
+            // println!("Other tray event: {:?}", event);
+        }
+    }
+}
+
+pub fn tray_setup(app: &tauri::App) -> Result<(), Box<(dyn std::error::Error + 'static)>> {
+    let info_item = MenuItem::with_id(app, "info", "Info", true, None::<&str>)?;
+    let undo_item = MenuItem::with_id(app, "undo", "Undo Last Rename", true, None::<&str>)?;
+    let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+
+    // Create the tray menu
+    let menu = Menu::with_items(app, &[&info_item, &undo_item, &quit_item])?;
+
+    // Create the system tray
+    TrayIconBuilder::with_id("main-tray")
+        .show_menu_on_left_click(true)
+        .icon(app.default_window_icon().unwrap().clone())
+        .menu(&menu)
+        .on_tray_icon_event(|tray, event| tray_icon_event_handler(tray, event))
+        .on_menu_event(|app, event| menu_event_handler(app, event))
+        .build(app)?;
+
+    Ok(())
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub async fn run() {
+    // Refuse to start a second instance, both would rename the same files
+    if let Err(e) = watcher::pid::acquire() {
+        if e.downcast_ref::<watcher::pid::AlreadyRunning>().is_some() {
+            error!("{}", e);
+        } else {
+            // without the lock nothing stops a second watcher from starting
+            error!("Failed to lock the PID file, not starting: {:?}", e);
+        }
+        return;
+    }
+
+    // Start the daemon in a parallel thread when the app starts
+    tokio::spawn(async {
+        info!("Starting Goggles daemon in background...");
+        watcher::daemon::run_with_options(Default::default()).await;
+    });
+
+    let app = tauri::Builder::default()
+        .manage(watcher::naming::BackendCache::default())
+        .plugin(tauri_plugin_autostart::init(Default::default(), None))
+        .plugin(tauri_plugin_positioner::init())
+        .setup(|app| tray_setup(app))
+        .plugin(tauri_plugin_opener::init())
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            close_window,
+            minimize_window,
+            maximize_window,
+            update_config_address,
+            get_config_address,
+            update_config_server,
+            get_config_server,
+            get_config_sources,
+            get_finder_selection,
+            process_image_with_ai,
+            preview_rename,
+            apply_rename,
+            get_job_statuses,
+            update_name_template,
+            get_name_template,
+            preview_name_template,
+            get_rename_history,
+            undo_rename,
+            undo_last_rename
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application");
+
+    app.run(|_app, event| match event {
+        tauri::RunEvent::ExitRequested { api, .. } => {
+            api.prevent_exit();
+        }
+        _ => {}
+    });
+}
@@ -1,10 +1,34 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use clap::Parser;
+use goggles_lib::cli::Cli;
+
 #[tokio::main]
 async fn main() {
-    // Initialize the logger to display info! logs on terminal
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let cli = Cli::parse();
+
+    // Initialize the logger to display info! logs on terminal, an explicit
+    // --log-level wins over RUST_LOG
+    let mut logger = match &cli.log_level {
+        Some(filter) => {
+            let mut logger = env_logger::Builder::from_env(
+                env_logger::Env::new().write_style(env_logger::DEFAULT_WRITE_STYLE_ENV),
+            );
+            logger.parse_filters(filter);
+            logger
+        }
+        None => env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")),
+    };
+    logger.init();
 
-    goggles_lib::run().await;
+    match cli.command {
+        Some(command) => goggles_lib::cli::run(command).await,
+        #[cfg(feature = "gui")]
+        None => goggles_lib::run().await,
+        #[cfg(not(feature = "gui"))]
+        None => {
+            <Cli as clap::CommandFactory>::command().print_help().ok();
+        }
+    }
 }
@@ -1,56 +1,453 @@
 #![allow(deprecated)]
-use log::info;
+use async_trait::async_trait;
+use log::{info, warn};
 use reqwest::multipart;
 use serde::Deserialize;
-use std::{fs::File, path::PathBuf};
+use std::path::{Path, PathBuf};
 
+use crate::watcher::config::GogglesConfig;
+use crate::watcher::error::GogglesError;
+use crate::watcher::http::build_client;
+use crate::watcher::naming::{NameSuggestion, NamingBackend, NamingContext};
+use crate::watcher::privacy::PrivacyRules;
+use crate::watcher::retry::{retry, AttemptError, CircuitBreaker, RetryPolicy, SERVER_CIRCUIT};
+use crate::watcher::upload::{prepare_upload, PreparedUpload, UploadOptions};
+
+// mirrors the server response, only `generated_filename` is used
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct ApiResponse {
     success: bool,
-    originalFilename: String,
-    generatedFilename: String,
-    imageSize: u64,
-    mimeType: String,
+    original_filename: String,
+    generated_filename: String,
+    image_size: u64,
+    mime_type: String,
+}
+
+/// Body of 4xx/5xx responses, see `backend/controller/aiController.ts`.
+#[derive(Debug, Deserialize)]
+struct ApiErrorResponse {
+    error: String,
+    #[serde(default)]
+    details: Option<String>,
+}
+
+/// Maps an error response onto the variants the UI knows about.
+fn server_error(status: reqwest::StatusCode, body: &str) -> GogglesError {
+    let Ok(response) = serde_json::from_str::<ApiErrorResponse>(body) else {
+        if status == reqwest::StatusCode::PAYMENT_REQUIRED {
+            return GogglesError::InsufficientCredits { details: None };
+        }
+        return GogglesError::Server {
+            status: status.as_u16(),
+            message: status.to_string(),
+            details: (!body.trim().is_empty()).then(|| body.trim().to_string()),
+        };
+    };
+
+    if response.error == "Insufficient credits" || status == reqwest::StatusCode::PAYMENT_REQUIRED {
+        GogglesError::InsufficientCredits {
+            details: response.details,
+        }
+    } else if response.error.starts_with("No address provided") {
+        GogglesError::Config("No wallet address configured".to_string())
+    } else {
+        GogglesError::Server {
+            status: status.as_u16(),
+            message: response.error,
+            details: response.details,
+        }
+    }
+}
+
+/// Whether another attempt can get past `error`. Besides timeouts and
+/// refused connections that covers connections dropped mid-request, which
+/// pooled keep-alive connections run into when the server closes them.
+fn is_transient(error: &reqwest::Error) -> bool {
+    if error.is_timeout() || error.is_connect() || error.is_request() || error.is_body() {
+        return true;
+    }
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+            ) {
+                return true;
+            }
+        }
+        source = cause.source();
+    }
+    false
 }
 
 #[derive(Debug, Clone)]
-pub struct OpenAI {}
+pub struct OpenAI {
+    server_url: String,
+    // shared by every request so connections and TLS sessions are reused
+    client: reqwest::Client,
+    auth_header: Option<String>,
+    retry_policy: RetryPolicy,
+    circuit: &'static CircuitBreaker,
+    upload: UploadOptions,
+    privacy: PrivacyRules,
+}
 
 impl OpenAI {
-    pub fn new() -> Self {
-        Self {}
+    /// Fails on a proxy or CA bundle the client can't use, rather than
+    /// sending requests around them.
+    pub fn from_config(config: &GogglesConfig) -> Result<Self, GogglesError> {
+        Ok(Self {
+            server_url: config.server_url.trim_end_matches('/').to_string(),
+            client: build_client(config)?,
+            auth_header: config.server_auth_header.clone(),
+            retry_policy: RetryPolicy {
+                max_retries: config.server_max_retries,
+                ..RetryPolicy::default()
+            },
+            circuit: &SERVER_CIRCUIT,
+            upload: UploadOptions::from_config(config),
+            privacy: PrivacyRules::new(&config.privacy_rules),
+        })
     }
 
+    /// Asks the server to name `image_path`. Redact regions are only
+    /// applied to a `screenshot`.
     pub async fn get_name(
         &self,
         address: String,
         image_path: PathBuf,
+        screenshot: bool,
     ) -> Result<String, anyhow::Error> {
         info!("Sending request to private server for address: {}", address);
 
-        // Create multipart form data
+        // encode once, every attempt sends the same bytes
+        let options = self.upload_options(screenshot);
+        let upload =
+            tokio::task::spawn_blocking(move || prepare_upload(&image_path, &options)).await??;
+
+        retry(&self.retry_policy, self.circuit, || {
+            self.request_name(&address, &upload)
+        })
+        .await
+    }
+
+    fn upload_options(&self, screenshot: bool) -> UploadOptions {
+        let mut options = self.upload.clone();
+        // the regions are screen coordinates, meaningless on other images
+        if !screenshot {
+            options.redact_regions.clear();
+        }
+        options
+    }
+
+    /// A single request, with failures sorted into retryable or not.
+    async fn request_name(
+        &self,
+        address: &str,
+        upload: &PreparedUpload,
+    ) -> Result<String, AttemptError> {
+        // Create multipart form data, it can't be reused between attempts
+        let image = multipart::Part::bytes(upload.bytes.clone())
+            .file_name(upload.file_name.clone())
+            .mime_str(upload.mime_type)
+            .map_err(|e| AttemptError::Permanent(e.into()))?;
         let form = multipart::Form::new()
-            .text("address", address.clone())
-            .file("image", &image_path).await?;
+            .text("address", address.to_string())
+            .part("image", image);
 
         // Send request to your private server
-        let response = reqwest::Client::new()
-            .post("https://conjurer-production.up.railway.app/generate-filename")
-            .multipart(form)
-            .send()
-            .await?;
+        let mut request = self
+            .client
+            .post(format!("{}/generate-filename", self.server_url))
+            .multipart(form);
+        if let Some(auth_header) = &self.auth_header {
+            request = request.header(reqwest::header::AUTHORIZATION, auth_header);
+        }
+        let response = request.send().await.map_err(|e| {
+            if is_transient(&e) {
+                AttemptError::Transient(GogglesError::Network(e).into())
+            } else {
+                AttemptError::Permanent(GogglesError::Network(e).into())
+            }
+        })?;
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Server returned error status: {}",
-                response.status()
-            ));
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let error = server_error(status, &body);
+            warn!(
+                "Server returned {}: {}",
+                status,
+                error.details().unwrap_or(&body)
+            );
+            let error = error.into();
+            return Err(
+                if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    AttemptError::Transient(error)
+                } else {
+                    AttemptError::Permanent(error)
+                },
+            );
         }
 
         // Parse the response in ApiResponse struct
-        let response_text = response.text().await?;
-        let response_json: ApiResponse = serde_json::from_str(&response_text)?;
+        let response_text = response
+            .text()
+            .await
+            .map_err(|e| AttemptError::Transient(GogglesError::Network(e).into()))?;
+        let response_json: ApiResponse = serde_json::from_str(&response_text).map_err(|e| {
+            AttemptError::Permanent(GogglesError::InvalidResponse(e.to_string()).into())
+        })?;
+
+        Ok(response_json.generated_filename)
+    }
+}
+
+#[async_trait]
+impl NamingBackend for OpenAI {
+    fn id(&self) -> &str {
+        "openai"
+    }
+
+    async fn suggest_name(
+        &self,
+        path: &Path,
+        context: &NamingContext,
+    ) -> Result<NameSuggestion, anyhow::Error> {
+        if let Some(rule) = self.privacy.matching(path, context) {
+            return Err(GogglesError::Private {
+                path: path.to_path_buf(),
+                rule: rule.to_string(),
+            }
+            .into());
+        }
+        let name = self
+            .get_name(
+                context.address.clone(),
+                path.to_path_buf(),
+                context.screenshot,
+            )
+            .await?;
+        Ok(NameSuggestion {
+            name,
+            backend: self.id().to_string(),
+        })
+    }
+}
+
+/// Test helpers shared with the queue tests.
+#[cfg(test)]
+impl OpenAI {
+    /// A backend for `url` that retries fast and reports to `circuit`.
+    pub(crate) fn for_test(url: &str, circuit: &'static CircuitBreaker) -> Self {
+        let config = GogglesConfig {
+            server_url: url.to_string(),
+            ..GogglesConfig::default()
+        };
+        let mut backend = Self::from_config(&config).unwrap();
+        backend.retry_policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(5),
+        };
+        backend.circuit = circuit;
+        backend
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher::local::LocalBackend;
+    use crate::watcher::mock_server::{mock_server, OK_BODY};
+    use crate::watcher::naming::FallbackBackend;
+    use crate::watcher::privacy::RedactRegion;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn circuit(threshold: u32) -> &'static CircuitBreaker {
+        Box::leak(Box::new(CircuitBreaker::new(
+            threshold,
+            Duration::from_secs(60),
+        )))
+    }
+
+    fn image() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shot.png");
+        image::RgbImage::new(8, 8).save(&path).unwrap();
+        (dir, path)
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_until_success() {
+        let (url, requests) = mock_server(vec![(503, "{}"), (503, "{}"), (200, OK_BODY)]);
+        let (_dir, path) = image();
+
+        let name = OpenAI::for_test(&url, circuit(10))
+            .get_name("0x1".to_string(), path, false)
+            .await
+            .unwrap();
+
+        assert_eq!(name, "mock-name");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_connections_closed_without_a_reply() {
+        let (url, requests) = mock_server(vec![(0, ""), (200, OK_BODY)]);
+        let (_dir, path) = image();
+
+        let name = OpenAI::for_test(&url, circuit(10))
+            .get_name("0x1".to_string(), path, false)
+            .await
+            .unwrap();
+
+        assert_eq!(name, "mock-name");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn network_errors_keep_their_cause() {
+        let (url, requests) = mock_server(vec![(0, "")]);
+        let (_dir, path) = image();
+        let circuit = circuit(10);
+
+        let error = OpenAI::for_test(&url, circuit)
+            .get_name("0x1".to_string(), path, false)
+            .await
+            .unwrap_err();
+
+        let error = error.downcast_ref::<GogglesError>().unwrap();
+        assert_eq!(error.kind(), "network");
+        assert!(std::error::Error::source(error).is_some());
+        // every attempt was retried and counted against the server
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (url, requests) = mock_server(vec![(400, r#"{"error":"Bad image"}"#)]);
+        let (_dir, path) = image();
+
+        let error = OpenAI::for_test(&url, circuit(10))
+            .get_name("0x1".to_string(), path, false)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<GogglesError>(),
+            Some(GogglesError::Server { status: 400, .. })
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn decodes_insufficient_credits() {
+        // the backend reports this as a plain 400
+        let (url, requests) = mock_server(vec![(
+            400,
+            r#"{"error":"Insufficient credits","details":"You have insufficient credits"}"#,
+        )]);
+        let (_dir, path) = image();
+
+        let error = OpenAI::for_test(&url, circuit(10))
+            .get_name("0x1".to_string(), path, false)
+            .await
+            .unwrap_err();
+
+        let error = error.downcast_ref::<GogglesError>().unwrap();
+        assert_eq!(error.kind(), "insufficient_credits");
+        assert_eq!(error.details(), Some("You have insufficient credits"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn files_that_are_not_images_are_never_uploaded() {
+        let (url, requests) = mock_server(vec![(200, OK_BODY)]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pdf");
+        std::fs::write(&path, b"%PDF-1.7").unwrap();
+
+        let error = OpenAI::for_test(&url, circuit(10))
+            .get_name("0x1".to_string(), path, false)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<GogglesError>(),
+            Some(GogglesError::NotAnImage(_))
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn repeated_failures_open_the_circuit() {
+        let (url, requests) = mock_server(vec![(500, "{}")]);
+        let (_dir, path) = image();
+        let circuit = circuit(2);
+
+        // the request that opened the circuit reports its own failure, so
+        // it falls back instead of waiting for the server
+        let error = OpenAI::for_test(&url, circuit)
+            .get_name("0x1".to_string(), path, false)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<GogglesError>(),
+            Some(GogglesError::Server { status: 500, .. })
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(circuit.remaining().is_some());
+    }
+
+    #[tokio::test]
+    async fn private_files_are_never_uploaded() {
+        let (url, requests) = mock_server(vec![(200, OK_BODY)]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bank-statement.png");
+        image::RgbImage::new(8, 8).save(&path).unwrap();
+        let mut backend = OpenAI::for_test(&url, circuit(10));
+        backend.privacy = PrivacyRules::new(&["*bank*".to_string()]);
+        let backend = Arc::new(backend);
+
+        let error = backend
+            .suggest_name(&path, &NamingContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<GogglesError>(),
+            Some(GogglesError::Private { rule, .. }) if rule == "*bank*"
+        ));
+
+        // the fallback still names it, locally
+        let suggestion = FallbackBackend::new(backend, Arc::new(LocalBackend::new()))
+            .suggest_name(&path, &NamingContext::default())
+            .await
+            .unwrap();
+        assert_eq!(suggestion.backend, "local");
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn redacts_only_screenshots() {
+        let mut backend = OpenAI::for_test("http://127.0.0.1:1", circuit(10));
+        let menu_bar = RedactRegion {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 0.05,
+        };
+        backend.upload.redact_regions = vec![menu_bar];
 
-        Ok(response_json.generatedFilename)
+        assert_eq!(backend.upload_options(true).redact_regions, vec![menu_bar]);
+        assert!(backend.upload_options(false).redact_regions.is_empty());
     }
 }
@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+/// What to do when the generated name is already taken.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionPolicy {
+    /// Append `-2`, `-3`, ... until the name is free.
+    #[default]
+    Suffix,
+    /// Append the current local time, then a counter if still taken.
+    Timestamp,
+    /// Leave the file alone.
+    Skip,
+}
+
+/// Returns a free path for `path` under `policy`, or `None` when the file
+/// should be skipped.
+pub fn resolve_collision(path: &Path, policy: CollisionPolicy) -> Option<PathBuf> {
+    if !path.exists() {
+        return Some(path.to_path_buf());
+    }
+
+    match policy {
+        CollisionPolicy::Skip => None,
+        CollisionPolicy::Suffix => Some(with_counter(path)),
+        CollisionPolicy::Timestamp => {
+            let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+            let stamped = with_stem_suffix(path, &stamp);
+            if stamped.exists() {
+                Some(with_counter(&stamped))
+            } else {
+                Some(stamped)
+            }
+        }
+    }
+}
+
+fn with_counter(path: &Path) -> PathBuf {
+    let mut counter = 2;
+    loop {
+        let candidate = with_stem_suffix(path, &counter.to_string());
+        if !candidate.exists() {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+fn with_stem_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let filename = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+    path.with_file_name(filename)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn taken(dir: &Path, names: &[&str]) -> PathBuf {
+        for name in names {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        dir.join(names[0])
+    }
+
+    #[test]
+    fn free_names_are_kept_by_every_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("free.png");
+        for policy in [
+            CollisionPolicy::Suffix,
+            CollisionPolicy::Timestamp,
+            CollisionPolicy::Skip,
+        ] {
+            assert_eq!(resolve_collision(&path, policy), Some(path.clone()));
+        }
+    }
+
+    #[test]
+    fn suffix_counts_past_taken_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = taken(dir.path(), &["shot.png", "shot-2.png", "shot-3.png"]);
+        assert_eq!(
+            resolve_collision(&path, CollisionPolicy::Suffix),
+            Some(dir.path().join("shot-4.png"))
+        );
+    }
+
+    #[test]
+    fn timestamp_appends_the_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = taken(dir.path(), &["shot.png"]);
+        let resolved = resolve_collision(&path, CollisionPolicy::Timestamp).unwrap();
+        let name = resolved.file_name().unwrap().to_str().unwrap();
+
+        // shot-YYYYmmdd-HHMMSS.png
+        assert!(name.starts_with("shot-") && name.ends_with(".png"));
+        assert_eq!(name.len(), "shot-20250101-120000.png".len());
+        assert!(!resolved.exists());
+    }
+
+    #[test]
+    fn timestamp_falls_back_to_a_counter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = taken(dir.path(), &["shot.png"]);
+        let stamped = resolve_collision(&path, CollisionPolicy::Timestamp).unwrap();
+        fs::write(&stamped, b"").unwrap();
+
+        let resolved = resolve_collision(&path, CollisionPolicy::Timestamp).unwrap();
+        assert!(!resolved.exists());
+        assert_ne!(resolved, stamped);
+    }
+
+    #[test]
+    fn skip_gives_up_on_taken_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = taken(dir.path(), &["shot.png"]);
+        assert_eq!(resolve_collision(&path, CollisionPolicy::Skip), None);
+    }
+}
@@ -1,12 +1,158 @@
+use log::info;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::watcher::collision::CollisionPolicy;
+use crate::watcher::error::GogglesError;
+use crate::watcher::fsops::write_atomic;
+use crate::watcher::http::build_client;
+use crate::watcher::layers::load_layered;
+use crate::watcher::migrations::{config_version, migrate, CURRENT_CONFIG_VERSION};
+use crate::watcher::paths::AppPaths;
+use crate::watcher::privacy::RedactRegion;
+use crate::watcher::template::{NameTemplate, DEFAULT_TEMPLATE};
+use crate::watcher::upload::{UploadFormat, MIN_DIMENSION};
+use crate::watcher::utils::get_screenshot_dir;
+
+pub const DEFAULT_SERVER_URL: &str = "https://conjurer-production.up.railway.app";
+
+/// Set once by the CLI's `--config` flag, replaces the default `config.json`.
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct GogglesConfig {
+    /// Schema version, see `migrations`. Missing in files older than v1.
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
     pub updated_at: u64,
+    #[serde(default)]
     pub address: String,
+    #[serde(default = "default_server_url")]
+    pub server_url: String,
+    /// Whole-request timeout, 60 seconds when unset.
+    #[serde(default)]
+    pub server_timeout_secs: Option<u64>,
+    #[serde(default = "default_server_connect_timeout_secs")]
+    pub server_connect_timeout_secs: u64,
+    /// Proxy for server requests, e.g. `http://proxy.corp:8080`.
+    #[serde(default)]
+    pub server_proxy: Option<String>,
+    /// PEM bundle of extra CAs to trust, for TLS-inspecting proxies.
+    #[serde(default)]
+    pub server_ca_bundle: Option<PathBuf>,
+    #[serde(default)]
+    pub server_auth_header: Option<String>,
+    /// Longest side of images sent to the server, larger ones are scaled
+    /// down. At least 256.
+    #[serde(default = "default_upload_max_dimension")]
+    pub upload_max_dimension: u32,
+    /// Size budget for a single upload in bytes.
+    #[serde(default = "default_upload_max_bytes")]
+    pub upload_max_bytes: usize,
+    #[serde(default)]
+    pub upload_format: UploadFormat,
+    /// Screen areas covered before a screenshot is uploaded, e.g. the menu bar.
+    #[serde(default)]
+    pub redact_regions: Vec<RedactRegion>,
+    /// Glob patterns for files that are never uploaded and get a local
+    /// name instead, matched against the filename and frontmost app.
+    #[serde(default)]
+    pub privacy_rules: Vec<String>,
+    /// Retries of a failed request before giving up on the server.
+    #[serde(default = "default_server_max_retries")]
+    pub server_max_retries: u32,
+    #[serde(default)]
+    pub collision_policy: CollisionPolicy,
+    #[serde(default = "default_name_template")]
+    pub name_template: String,
+    /// Folders to watch; empty means just the system screenshot folder.
+    #[serde(default)]
+    pub watched_folders: Vec<WatchedFolder>,
+    /// Files the daemon renames at the same time.
+    #[serde(default = "default_max_concurrent_jobs")]
+    pub max_concurrent_jobs: usize,
+    /// Detected files allowed to wait for a worker before intake stalls.
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+}
+
+/// A watched folder and the rules applied to files created in it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WatchedFolder {
+    /// Absolute path, or relative to home when it starts with `~/`.
+    pub path: PathBuf,
+    #[serde(default)]
+    pub recursive: bool,
+    /// Glob patterns matched against the filename; empty matches everything.
+    #[serde(default)]
+    pub filters: Vec<String>,
+    /// Overrides the global `name_template` for this folder.
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Only rename macOS screenshots, the way the screenshot folder is handled.
+    #[serde(default)]
+    pub screenshots_only: bool,
+    /// Name files locally, nothing from this folder is sent to the server.
+    #[serde(default)]
+    pub never_upload: bool,
+}
+
+fn default_server_url() -> String {
+    DEFAULT_SERVER_URL.to_string()
+}
+
+fn default_server_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_upload_max_dimension() -> u32 {
+    1024
+}
+
+fn default_upload_max_bytes() -> usize {
+    512 * 1024
+}
+
+fn default_server_max_retries() -> u32 {
+    3
+}
+
+fn default_name_template() -> String {
+    DEFAULT_TEMPLATE.to_string()
+}
+
+fn default_max_concurrent_jobs() -> usize {
+    2
+}
+
+fn default_queue_capacity() -> usize {
+    64
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A backup name for `config_path` at `version` that no earlier migration
+/// used, e.g. `config.json.v0.1717171717.bak`.
+fn backup_path(config_path: &Path, version: u32) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut backup = config_path.with_extension(format!("json.v{}.{}.bak", version, now));
+    let mut copy = 1;
+    while backup.exists() {
+        backup = config_path.with_extension(format!("json.v{}.{}-{}.bak", version, now, copy));
+        copy += 1;
+    }
+    backup
 }
 
 impl GogglesConfig {
@@ -14,33 +160,190 @@ impl GogglesConfig {
         self.address.clone()
     }
 
-    pub fn get_config_path() -> String {
-        if let Some(home_dir) = dirs::home_dir() {
-            let config_dir = home_dir.join(".goggles");
-            if !config_dir.exists() {
-                let _ = fs::create_dir_all(&config_dir);
+    /// Equal apart from `updated_at`, which is a fresh timestamp whenever
+    /// a layer leaves it out.
+    pub fn same_settings(&self, other: &Self) -> bool {
+        *self
+            == Self {
+                updated_at: self.updated_at,
+                ..other.clone()
             }
-            config_dir.join("config.json").to_string_lossy().to_string()
-        } else {
-            "config.json".to_string()
+    }
+
+    /// Whether the naming backend and its HTTP client built from `other`
+    /// would be the same as the ones built from `self`.
+    pub fn same_backend_settings(&self, other: &Self) -> bool {
+        self.server_url == other.server_url
+            && self.server_auth_header == other.server_auth_header
+            && self.server_timeout_secs == other.server_timeout_secs
+            && self.server_connect_timeout_secs == other.server_connect_timeout_secs
+            && self.server_proxy == other.server_proxy
+            && self.server_ca_bundle == other.server_ca_bundle
+            && self.server_max_retries == other.server_max_retries
+            && self.upload_max_dimension == other.upload_max_dimension
+            && self.upload_max_bytes == other.upload_max_bytes
+            && self.upload_format == other.upload_format
+            && self.redact_regions == other.redact_regions
+            && self.privacy_rules == other.privacy_rules
+    }
+
+    /// Configured folders, or the screenshot folder when none are set.
+    pub fn get_watched_folders(&self) -> Vec<WatchedFolder> {
+        if !self.watched_folders.is_empty() {
+            return self.watched_folders.clone();
+        }
+
+        vec![WatchedFolder {
+            path: get_screenshot_dir(),
+            recursive: false,
+            filters: vec![],
+            template: None,
+            enabled: true,
+            screenshots_only: true,
+            never_upload: false,
+        }]
+    }
+
+    /// Checks everything that would otherwise only fail once a file is
+    /// being processed.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        reqwest::Url::parse(&self.server_url)
+            .map_err(|e| anyhow::anyhow!("Invalid server_url {:?}: {}", self.server_url, e))?;
+        NameTemplate::parse(&self.name_template)
+            .map_err(|e| anyhow::anyhow!("Invalid name_template: {}", e))?;
+        // catches bad proxy URLs and CA bundles before the daemon uses them
+        build_client(self)?;
+        if self.max_concurrent_jobs == 0 {
+            return Err(anyhow::anyhow!("max_concurrent_jobs must be at least 1"));
+        }
+        if self.queue_capacity == 0 {
+            return Err(anyhow::anyhow!("queue_capacity must be at least 1"));
+        }
+        if self.upload_max_dimension < MIN_DIMENSION {
+            return Err(anyhow::anyhow!(
+                "upload_max_dimension must be at least {}",
+                MIN_DIMENSION
+            ));
+        }
+        if self.upload_max_bytes == 0 {
+            return Err(anyhow::anyhow!("upload_max_bytes must be at least 1"));
+        }
+        for region in &self.redact_regions {
+            region.validate()?;
+        }
+        for rule in &self.privacy_rules {
+            glob::Pattern::new(rule)
+                .map_err(|e| anyhow::anyhow!("Invalid privacy rule {:?}: {}", rule, e))?;
         }
+
+        for folder in &self.watched_folders {
+            if let Some(template) = &folder.template {
+                NameTemplate::parse(template).map_err(|e| {
+                    anyhow::anyhow!("Invalid template for {:?}: {}", folder.path, e)
+                })?;
+            }
+            for filter in &folder.filters {
+                glob::Pattern::new(filter).map_err(|e| {
+                    anyhow::anyhow!("Invalid filter {:?} for {:?}: {}", filter, folder.path, e)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Points every load and save at `path` instead of the default file.
+    /// Only the first call has an effect.
+    pub fn set_config_path(path: PathBuf) {
+        let _ = CONFIG_PATH_OVERRIDE.set(path);
+    }
+
+    pub fn get_config_path() -> Result<PathBuf, anyhow::Error> {
+        if let Some(path) = CONFIG_PATH_OVERRIDE.get() {
+            return Ok(path.clone());
+        }
+        Ok(AppPaths::new()?.config_file())
     }
 
     pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        let config_path = Self::get_config_path();
-        if Path::new(&config_path).exists() {
-            let content = fs::read_to_string(&config_path)?;
-            let config: GogglesConfig = serde_json::from_str(&content)?;
-            Ok(config)
-        } else {
-            Ok(Self::default())
+        match Self::load_user_json()? {
+            Some(raw) => Ok(serde_json::from_value(raw)?),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The effective config, merged from every layer in `layers`.
+    pub fn load_effective() -> Result<Self, anyhow::Error> {
+        Ok(load_layered()?.config)
+    }
+
+    /// Raw contents of `config.json`, migrated to the current version.
+    pub fn load_user_json() -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
+        Self::load_user_json_from(&Self::get_config_path()?)
+    }
+
+    pub(crate) fn load_user_json_from(
+        config_path: &Path,
+    ) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
+        if !config_path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(config_path)?;
+        let raw: serde_json::Value = serde_json::from_str(&content)?;
+
+        let version = config_version(&raw)?;
+        if version == CURRENT_CONFIG_VERSION {
+            return Ok(Some(raw));
         }
+
+        let migrated = migrate(raw)?;
+        // refuse to touch the file when the result isn't a usable config
+        serde_json::from_value::<GogglesConfig>(migrated.clone())?;
+
+        // keep the old file around in case the migration loses something
+        let backup_path = backup_path(config_path, version);
+        fs::copy(config_path, &backup_path)?;
+        write_atomic(
+            config_path,
+            serde_json::to_string_pretty(&migrated)?.as_bytes(),
+        )?;
+        info!(
+            "Migrated config from version {} to {}, backup at {}",
+            version,
+            CURRENT_CONFIG_VERSION,
+            backup_path.display()
+        );
+        Ok(Some(migrated))
+    }
+
+    /// Writes `keys` of this config to `config.json`. Every other key keeps
+    /// what the user wrote there, or stays unset so lower layers like the
+    /// system file still apply.
+    fn save_keys(&self, keys: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        self.save_keys_to(&Self::get_config_path()?, keys)
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let config_path = Self::get_config_path();
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(&config_path, content)?;
+    pub(crate) fn save_keys_to(
+        &self,
+        config_path: &Path,
+        keys: &[&str],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut raw = match Self::load_user_json_from(config_path)? {
+            Some(serde_json::Value::Object(raw)) => raw,
+            _ => serde_json::Map::new(),
+        };
+
+        let serde_json::Value::Object(values) = serde_json::to_value(self)? else {
+            return Err("Config is not an object".into());
+        };
+        for key in keys.iter().chain(&["updated_at"]) {
+            if let Some(value) = values.get(*key) {
+                raw.insert(key.to_string(), value.clone());
+            }
+        }
+        raw.insert("version".to_string(), CURRENT_CONFIG_VERSION.into());
+
+        write_atomic(config_path, serde_json::to_string_pretty(&raw)?.as_bytes())?;
         Ok(())
     }
 
@@ -53,7 +356,42 @@ impl GogglesConfig {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        self.save()?;
+        self.save_keys(&["address"])?;
+        Ok(())
+    }
+
+    pub fn update_name_template(
+        &mut self,
+        template: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        NameTemplate::parse(&template).map_err(|e| e.to_string())?;
+        self.name_template = template;
+        self.updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.save_keys(&["name_template"])?;
+        Ok(())
+    }
+
+    pub fn update_server(
+        &mut self,
+        server_url: String,
+        timeout_secs: Option<u64>,
+        auth_header: Option<String>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // a bad URL would make the daemon reject every later config change
+        reqwest::Url::parse(&server_url).map_err(|e| {
+            GogglesError::Config(format!("Invalid server_url {:?}: {}", server_url, e))
+        })?;
+        self.server_url = server_url;
+        self.server_timeout_secs = timeout_secs;
+        self.server_auth_header = auth_header;
+        self.updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.save_keys(&["server_url", "server_timeout_secs", "server_auth_header"])?;
         Ok(())
     }
 }
@@ -61,11 +399,107 @@ impl GogglesConfig {
 impl Default for GogglesConfig {
     fn default() -> Self {
         Self {
+            version: CURRENT_CONFIG_VERSION,
             updated_at: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
             address: String::new(),
+            server_url: default_server_url(),
+            server_timeout_secs: None,
+            server_connect_timeout_secs: default_server_connect_timeout_secs(),
+            server_proxy: None,
+            server_ca_bundle: None,
+            server_auth_header: None,
+            server_max_retries: default_server_max_retries(),
+            upload_max_dimension: default_upload_max_dimension(),
+            upload_max_bytes: default_upload_max_bytes(),
+            upload_format: UploadFormat::default(),
+            redact_regions: vec![],
+            privacy_rules: vec![],
+            collision_policy: CollisionPolicy::default(),
+            name_template: default_name_template(),
+            watched_folders: vec![],
+            max_concurrent_jobs: default_max_concurrent_jobs(),
+            queue_capacity: default_queue_capacity(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_server_urls_before_saving() {
+        let mut config = GogglesConfig::default();
+        let error = config
+            .update_server("not a url".to_string(), None, None)
+            .unwrap_err();
+
+        assert!(error.to_string().contains("Invalid server_url"));
+        assert_eq!(config.server_url, DEFAULT_SERVER_URL);
+    }
+
+    #[test]
+    fn compares_settings_without_the_timestamp() {
+        let config = GogglesConfig::default();
+        let later = GogglesConfig {
+            updated_at: config.updated_at + 60,
+            ..config.clone()
+        };
+        assert!(config.same_settings(&later));
+        assert!(config.same_backend_settings(&later));
+
+        let retemplated = GogglesConfig {
+            name_template: "{date}-{ai}".to_string(),
+            ..later.clone()
+        };
+        assert!(!config.same_settings(&retemplated));
+        assert!(config.same_backend_settings(&retemplated));
+
+        let proxied = GogglesConfig {
+            server_proxy: Some("http://proxy.corp:8080".to_string()),
+            ..later
+        };
+        assert!(!config.same_backend_settings(&proxied));
+    }
+
+    #[test]
+    fn keeps_a_backup_of_every_migration() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.json");
+        let original = r#"{"address":"0x1234","updated_at":1717171717}"#;
+
+        for _ in 0..2 {
+            fs::write(&config_path, original).unwrap();
+            let migrated = GogglesConfig::load_user_json_from(&config_path)
+                .unwrap()
+                .unwrap();
+            assert_eq!(migrated["address"], "0x1234");
+        }
+
+        let mut names: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert_eq!(names[0], "config.json");
+        for backup in &names[1..] {
+            assert!(
+                backup.starts_with("config.json.v0.") && backup.ends_with(".bak"),
+                "{}",
+                backup
+            );
+            assert_eq!(
+                fs::read_to_string(dir.path().join(backup)).unwrap(),
+                original
+            );
         }
+
+        let migrated: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        assert_eq!(migrated["version"], CURRENT_CONFIG_VERSION);
     }
 }
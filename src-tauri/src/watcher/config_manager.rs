@@ -0,0 +1,209 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::watch;
+
+use crate::watcher::config::GogglesConfig;
+use crate::watcher::layers::{get_system_config_path, get_user_toml_path};
+
+/// Editors often write a file in several steps, wait for them to settle.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+fn load_validated() -> Result<GogglesConfig, anyhow::Error> {
+    let config = GogglesConfig::load_effective()?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Loads the config and keeps it up to date on a background thread.
+///
+/// Every valid change to one of the config files is broadcast on the
+/// returned channel.
+/// Invalid edits are logged and ignored, receivers keep the last good config.
+/// The thread stops once every receiver is dropped.
+pub fn spawn_config_manager() -> watch::Receiver<GogglesConfig> {
+    let initial = load_validated().unwrap_or_else(|e| {
+        error!("Invalid config, using defaults: {}", e);
+        GogglesConfig::default()
+    });
+    let (tx, rx) = watch::channel(initial);
+
+    thread::spawn(move || {
+        let result = config_paths()
+            .and_then(|config_paths| watch_config(&tx, &config_paths, load_validated));
+        if let Err(e) = result {
+            error!("Config watcher stopped: {:?}", e);
+        }
+    });
+
+    rx
+}
+
+fn config_paths() -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut config_paths = vec![GogglesConfig::get_config_path()?, get_system_config_path()];
+    config_paths.extend(get_user_toml_path());
+    Ok(config_paths)
+}
+
+fn watch_dir(watcher: &mut RecommendedWatcher, dir: &Path) {
+    if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+        error!("Failed to watch {}: {:?}", dir.display(), e);
+    }
+}
+
+fn watch_config(
+    tx: &watch::Sender<GogglesConfig>,
+    config_paths: &[PathBuf],
+    load: impl Fn() -> Result<GogglesConfig, anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let (event_tx, event_rx) = channel();
+    let mut watcher: RecommendedWatcher = Watcher::new(event_tx, notify::Config::default())?;
+    // directories that don't exist yet are watched once they appear
+    let mut missing: Vec<&Path> = Vec::new();
+    for config_dir in config_paths.iter().filter_map(|path| path.parent()) {
+        // watch the directory, editors replace the file instead of writing it
+        if config_dir.exists() {
+            watch_dir(&mut watcher, config_dir);
+        } else if !missing.contains(&config_dir) {
+            missing.push(config_dir);
+        }
+    }
+    // catches edits made between the initial load and the watch starting
+    reload(tx, &load);
+
+    loop {
+        let waiting = missing.len();
+        missing.retain(|dir| {
+            if !dir.exists() {
+                return true;
+            }
+            info!("Watching new config directory {}", dir.display());
+            watch_dir(&mut watcher, dir);
+            false
+        });
+        if missing.len() != waiting {
+            // the file may have been written before the watch started
+            reload(tx, &load);
+        }
+
+        match event_rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Access(_))
+                    || !event.paths.iter().any(|path| {
+                        config_paths
+                            .iter()
+                            .any(|config_path| path.file_name() == config_path.file_name())
+                    })
+                {
+                    continue;
+                }
+
+                thread::sleep(DEBOUNCE);
+                while event_rx.try_recv().is_ok() {}
+                reload(tx, &load);
+            }
+            Ok(Err(e)) => error!("Config watch error: {:?}", e),
+            Err(RecvTimeoutError::Timeout) => {
+                if tx.is_closed() {
+                    return Ok(());
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow::anyhow!("Config watcher disconnected"));
+            }
+        }
+    }
+}
+
+fn reload(
+    tx: &watch::Sender<GogglesConfig>,
+    load: &impl Fn() -> Result<GogglesConfig, anyhow::Error>,
+) {
+    match load() {
+        Ok(config) => {
+            let changed = tx.send_if_modified(|current| {
+                if current.same_settings(&config) {
+                    return false;
+                }
+                *current = config;
+                true
+            });
+            if changed {
+                info!("Config reloaded");
+            }
+        }
+        Err(e) => error!("Ignoring invalid config, keeping the last good one: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Instant;
+
+    /// Waits for `condition` on the latest config, false after a while.
+    fn eventually(
+        rx: &watch::Receiver<GogglesConfig>,
+        condition: impl Fn(&GogglesConfig) -> bool,
+    ) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if condition(&rx.borrow()) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    fn write_template(path: &Path, template: &str) {
+        let config = GogglesConfig {
+            name_template: template.to_string(),
+            ..GogglesConfig::default()
+        };
+        fs::write(path, serde_json::to_string(&config).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_last_good_config_and_picks_up_new_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        // the directory doesn't exist when the watcher starts
+        let config_dir = dir.path().join("goggles");
+        let config_path = config_dir.join("config.json");
+
+        let (tx, rx) = watch::channel(GogglesConfig::default());
+        let paths = vec![config_path.clone()];
+        let load_path = config_path.clone();
+        let watcher = thread::spawn(move || {
+            watch_config(&tx, &paths, || {
+                let config: GogglesConfig = serde_json::from_str(&fs::read_to_string(&load_path)?)?;
+                config.validate()?;
+                Ok(config)
+            })
+        });
+
+        // give the watcher time to start before the directory appears
+        thread::sleep(DEBOUNCE * 4);
+        fs::create_dir(&config_dir).unwrap();
+        write_template(&config_path, "{date}-{ai}");
+        assert!(eventually(&rx, |config| config.name_template == "{date}-{ai}"));
+
+        // a broken edit, then one that fails validation
+        fs::write(&config_path, "{ not json").unwrap();
+        write_template(&config_path, "{nope}");
+        thread::sleep(DEBOUNCE * 4);
+        assert_eq!(rx.borrow().name_template, "{date}-{ai}");
+
+        write_template(&config_path, "{app}-{ai}");
+        assert!(eventually(&rx, |config| config.name_template == "{app}-{ai}"));
+
+        // the watcher stops with the last receiver
+        drop(rx);
+        watcher.join().unwrap().unwrap();
+    }
+}
@@ -1,75 +1,614 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
-    mpsc::{channel, RecvTimeoutError},
     Arc,
 };
+use std::time::{Duration, Instant, SystemTime};
 
 use log::{error, info};
-use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use tokio::signal;
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use tokio::{
+    signal,
+    sync::{mpsc, watch},
+};
+
+use crate::watcher::{
+    config::{GogglesConfig, WatchedFolder},
+    config_manager::spawn_config_manager,
+    image::SSManager,
+    journal::RenameJournal,
+    local::LocalBackend,
+    macos,
+    naming::{backend_from_config, BackendFactory, NamingBackend, NamingContext},
+    pending::PendingJobs,
+    queue::{Job, JobQueue},
+    template::NameTemplate,
+    utils::expand_home,
+};
+
+/// Extensions browsers and sync clients write to until a file is complete.
+const PARTIAL_EXTENSIONS: &[&str] = &[
+    "crdownload",
+    "part",
+    "partial",
+    "download",
+    "opdownload",
+    "tmp",
+    "temp",
+];
+
+/// How often a new file's size is checked while it is still being written.
+const SETTLE_POLL: Duration = Duration::from_secs(1);
+/// Downloads in progress remembered by `RenameIntake`, a bound for the
+/// ones that never complete.
+const MAX_TRACKED_DOWNLOADS: usize = 256;
+/// How long the old side of a rename waits for its new side.
+const RENAME_PAIR_WINDOW: Duration = Duration::from_secs(1);
+
+/// Overrides applied on top of the loaded config, set from the CLI.
+#[derive(Debug, Clone, Default)]
+pub struct DaemonOptions {
+    /// Watch these folders instead of the configured ones.
+    pub folders: Option<Vec<WatchedFolder>>,
+    /// Log renames without moving files.
+    pub dry_run: bool,
+}
+
+impl DaemonOptions {
+    fn apply(&self, mut config: GogglesConfig) -> GogglesConfig {
+        if let Some(folders) = &self.folders {
+            config.watched_folders = folders.clone();
+        }
+        config
+    }
+}
+
+/// A folder currently registered with the file watcher.
+struct ActiveFolder {
+    rule: WatchedFolder,
+    root: PathBuf,
+    filters: Vec<glob::Pattern>,
+    manager: SSManager,
+}
+
+impl ActiveFolder {
+    fn owns(&self, path: &Path) -> bool {
+        if self.rule.recursive {
+            path.starts_with(&self.root)
+        } else {
+            path.parent() == Some(self.root.as_path())
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        if self.filters.is_empty() {
+            return true;
+        }
+        let Some(filename) = path.file_name().and_then(|n| n.to_str()) else {
+            return false;
+        };
+        self.filters.iter().any(|filter| filter.matches(filename))
+    }
+}
+
+fn build_manager(
+    backend: &Arc<dyn NamingBackend>,
+    journal: &RenameJournal,
+    config: &GogglesConfig,
+    rule: &WatchedFolder,
+    dry_run: bool,
+) -> SSManager {
+    let raw_template = rule.template.as_ref().unwrap_or(&config.name_template);
+    let template = NameTemplate::parse(raw_template).unwrap_or_else(|e| {
+        error!(
+            "Invalid name template {:?}, using default: {}",
+            raw_template, e
+        );
+        NameTemplate::default()
+    });
+    let backend: Arc<dyn NamingBackend> = if rule.never_upload {
+        Arc::new(LocalBackend::new())
+    } else {
+        backend.clone()
+    };
+    SSManager::new(backend, journal.clone())
+        .with_collision_policy(config.collision_policy)
+        .with_template(template)
+        .with_dry_run(dry_run)
+}
+
+fn is_partial_download(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            PARTIAL_EXTENSIONS
+                .iter()
+                .any(|partial| ext.eq_ignore_ascii_case(partial))
+        })
+}
+
+/// Decides which renamed files count as new. Renames inside a folder are
+/// the user's, or an editor saving through a temp file, and are left
+/// alone. Completed downloads and files moved in from elsewhere are not.
+#[derive(Debug, Default)]
+struct RenameIntake {
+    /// Final names of the partial downloads seen so far.
+    downloads: HashSet<PathBuf>,
+    /// Old names of recent renames still waiting for their new side, with
+    /// the watcher's tracker when it pairs them (the inotify cookie).
+    moves_out: Vec<(Option<usize>, PathBuf, Instant)>,
+}
+
+impl RenameIntake {
+    /// Remembers the name `partial` gets once the download completes.
+    fn note_partial(&mut self, partial: &Path) {
+        let Some(stem) = partial.file_stem() else {
+            return;
+        };
+        if self.downloads.len() >= MAX_TRACKED_DOWNLOADS {
+            self.downloads.clear();
+        }
+        self.downloads.insert(partial.with_file_name(stem));
+    }
+
+    /// Remembers the old side of a rename reported on its own.
+    fn note_move_out(&mut self, tracker: Option<usize>, source: &Path) {
+        self.expire_moves_out();
+        if self.moves_out.len() >= MAX_TRACKED_DOWNLOADS {
+            self.moves_out.remove(0);
+        }
+        self.moves_out
+            .push((tracker, source.to_path_buf(), Instant::now()));
+    }
+
+    fn expire_moves_out(&mut self) {
+        self.moves_out
+            .retain(|(_, _, noted)| noted.elapsed() < RENAME_PAIR_WINDOW);
+    }
 
-use crate::watcher::{ai::OpenAI, config, image::SSManager, pid, utils::get_screenshot_dir};
+    /// The old side paired with a new side carrying `tracker`. Without a
+    /// tracker, e.g. on Windows, the latest untracked one.
+    fn take_move_out(&mut self, tracker: Option<usize>) -> Option<PathBuf> {
+        self.expire_moves_out();
+        let index = match tracker {
+            Some(_) => self.moves_out.iter().position(|(t, _, _)| *t == tracker),
+            None => self.moves_out.iter().rposition(|(t, _, _)| t.is_none()),
+        }?;
+        Some(self.moves_out.remove(index).1)
+    }
+
+    /// Whether `target`, renamed from `source` in `root`, is a new file.
+    /// `source` is only known when the watcher reports both sides in one
+    /// event, otherwise it is looked up from the old side seen before.
+    fn accepts(
+        &mut self,
+        mode: RenameMode,
+        tracker: Option<usize>,
+        source: Option<&Path>,
+        target: &Path,
+        root: &Path,
+    ) -> bool {
+        let source = match mode {
+            RenameMode::To => match self.take_move_out(tracker) {
+                // inotify follows up with a `Both` event for the pair
+                Some(_) if tracker.is_some() => return false,
+                Some(source) => Some(source),
+                // the source is outside every watched folder
+                None => {
+                    self.downloads.remove(target);
+                    return true;
+                }
+            },
+            _ => source.map(Path::to_path_buf),
+        };
+        if self.downloads.remove(target) {
+            return true;
+        }
+        match mode {
+            RenameMode::To | RenameMode::Both => source
+                .is_some_and(|source| is_partial_download(&source) || !source.starts_with(root)),
+            _ => false,
+        }
+    }
+}
+
+/// Waits until `path` stops changing between two polls. False once it is
+/// gone, e.g. renamed to its final name, or when it settles empty, like
+/// the placeholder Firefox creates next to a `.part` download.
+async fn wait_until_settled(path: &Path) -> bool {
+    let stamp = |path: &Path| -> Option<(u64, Option<SystemTime>)> {
+        let metadata = std::fs::metadata(path).ok()?;
+        metadata
+            .is_file()
+            .then(|| (metadata.len(), metadata.modified().ok()))
+    };
+
+    let Some(mut last) = stamp(path) else {
+        return false;
+    };
+    loop {
+        tokio::time::sleep(SETTLE_POLL).await;
+        let Some(current) = stamp(path) else {
+            return false;
+        };
+        if current == last {
+            return current.0 > 0;
+        }
+        last = current;
+    }
+}
+
+/// The most specific active folder that `path` belongs to.
+fn owning_folder<'a>(active: &'a [ActiveFolder], path: &Path) -> Option<&'a ActiveFolder> {
+    active
+        .iter()
+        .filter(|folder| folder.owns(path))
+        .max_by_key(|folder| folder.root.components().count())
+}
+
+/// Queues the jobs left over from the last run.
+async fn resume_pending(
+    queue: &JobQueue,
+    pending: &PendingJobs,
+    active: &[ActiveFolder],
+    config: &GogglesConfig,
+) {
+    let jobs = match pending.list() {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            error!("Failed to read pending jobs: {:?}", e);
+            return;
+        }
+    };
+
+    for job in jobs {
+        let Some(folder) = owning_folder(active, &job.path) else {
+            info!(
+                "Dropping pending file outside watched folders: {:?}",
+                job.path
+            );
+            pending.remove(&job.path).ok();
+            continue;
+        };
+        info!("Resuming pending file: {:?}", job.path);
+        queue
+            .submit(Job {
+                path: job.path,
+                context: NamingContext {
+                    screenshot: job.screenshot,
+                    ..NamingContext::new(config.get_config_address())
+                },
+                manager: folder.manager.clone(),
+                screenshot: job.screenshot,
+            })
+            .await;
+    }
+}
+
+/// Brings the watcher in line with the enabled folders in `config`.
+fn sync_watches(
+    watcher: &mut RecommendedWatcher,
+    active: &mut Vec<ActiveFolder>,
+    config: &GogglesConfig,
+    backend: &Arc<dyn NamingBackend>,
+    journal: &RenameJournal,
+    dry_run: bool,
+) {
+    let rules: Vec<WatchedFolder> = config
+        .get_watched_folders()
+        .into_iter()
+        .filter(|rule| rule.enabled)
+        .collect();
+
+    active.retain(|folder| {
+        let keep = rules.contains(&folder.rule);
+        if !keep {
+            info!("Stopped watching {}", folder.root.display());
+            watcher.unwatch(&folder.root).ok();
+        }
+        keep
+    });
+
+    // global settings may have changed too
+    for folder in active.iter_mut() {
+        folder.manager = build_manager(backend, journal, config, &folder.rule, dry_run);
+    }
+
+    for rule in rules {
+        if active.iter().any(|folder| folder.rule == rule) {
+            continue;
+        }
 
-pub async fn daemon(shutdown: Arc<AtomicBool>) {
-    let screenshot_dir = get_screenshot_dir();
-    info!("Goggles is running on {}", screenshot_dir.display());
+        let root = expand_home(&rule.path);
+        // events carry canonical paths, e.g. /private/var on macOS
+        let root = root.canonicalize().unwrap_or(root);
+        let mode = if rule.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        if let Err(e) = watcher.watch(&root, mode) {
+            error!("Failed to watch {}: {:?}", root.display(), e);
+            continue;
+        }
 
-    let (tx, rx) = channel();
+        let filters = rule
+            .filters
+            .iter()
+            .filter_map(|filter| match glob::Pattern::new(filter) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    error!("Invalid filter {:?}: {}", filter, e);
+                    None
+                }
+            })
+            .collect();
+        info!("Goggles is running on {}", root.display());
+        active.push(ActiveFolder {
+            manager: build_manager(backend, journal, config, &rule, dry_run),
+            rule,
+            root,
+            filters,
+        });
+    }
+}
 
-    let mut watcher: RecommendedWatcher =
-        Watcher::new(tx, notify::Config::default()).expect("Failed to create watcher");
-    watcher
-        .watch(&screenshot_dir, RecursiveMode::NonRecursive)
-        .expect("Failed to watch directory");
+pub async fn daemon(
+    shutdown: Arc<AtomicBool>,
+    mut config_rx: watch::Receiver<GogglesConfig>,
+    make_backend: BackendFactory,
+    options: DaemonOptions,
+) {
+    // events are only queued here, renaming happens on the job queue
+    let (tx, mut rx) = mpsc::unbounded_channel();
 
-    let ai = OpenAI::new();
-    let ss_controller = SSManager::new(ai);
+    let mut watcher: RecommendedWatcher = Watcher::new(
+        move |event| {
+            let _ = tx.send(event);
+        },
+        notify::Config::default(),
+    )
+    .expect("Failed to create watcher");
+
+    let journal = match RenameJournal::open() {
+        Ok(journal) => journal,
+        Err(e) => {
+            error!("Failed to open rename journal: {:?}", e);
+            return;
+        }
+    };
+
+    let mut active: Vec<ActiveFolder> = Vec::new();
+    let mut intake = RenameIntake::default();
+    let mut config = options.apply(config_rx.borrow_and_update().clone());
+    let mut backend = make_backend(&config).unwrap_or_else(|e| {
+        // nothing leaves the device until the config is fixed
+        error!("{}, naming files locally", e);
+        Arc::new(LocalBackend::new()) as Arc<dyn NamingBackend>
+    });
+    sync_watches(
+        &mut watcher,
+        &mut active,
+        &config,
+        &backend,
+        &journal,
+        options.dry_run,
+    );
+    // a dry run must not leave jobs behind for the real daemon
+    let pending = if options.dry_run {
+        None
+    } else {
+        match PendingJobs::open() {
+            Ok(pending) => Some(pending),
+            Err(e) => {
+                error!(
+                    "Failed to open pending jobs, they won't survive a restart: {:?}",
+                    e
+                );
+                None
+            }
+        }
+    };
+    let mut queue = JobQueue::new(
+        config.max_concurrent_jobs,
+        config.queue_capacity,
+        pending.clone(),
+    );
+    if let Some(pending) = &pending {
+        resume_pending(&queue, pending, &active, &config).await;
+    }
 
     info!("Setup complete, Goggles is ready!");
     while !shutdown.load(Ordering::Relaxed) {
-        match rx.recv_timeout(std::time::Duration::from_millis(100)) {
-            Ok(event) => {
-                if let Ok(Event {
-                    kind: EventKind::Create(_),
-                    paths,
-                    ..
-                }) = event
-                {
-                    for path in paths {
-                        info!("Detected new file: {:?}", path);
-                        // get address from config
-                        let config = config::GogglesConfig::load().unwrap();
-                        let address = config.get_config_address();
-                        info!("Processing file for address: {:?}", address);
-                        let resp = ss_controller.process_new_ss(address, &path).await;
-                        if let Err(e) = resp {
-                            error!("Error processing file: {:?}", e);
+        if config_rx.has_changed().unwrap_or(false) {
+            let previous = std::mem::replace(
+                &mut config,
+                options.apply(config_rx.borrow_and_update().clone()),
+            );
+            // keep the HTTP client and its connections when nothing it is
+            // built from changed
+            if !config.same_backend_settings(&previous) {
+                match make_backend(&config) {
+                    Ok(new_backend) => backend = new_backend,
+                    Err(e) => error!("{}, keeping the previous naming backend", e),
+                }
+            }
+            sync_watches(
+                &mut watcher,
+                &mut active,
+                &config,
+                &backend,
+                &journal,
+                options.dry_run,
+            );
+            if queue.concurrency() != config.max_concurrent_jobs.max(1)
+                || queue.capacity() != config.queue_capacity.max(1)
+            {
+                // the old queue finishes its jobs once its sender is gone
+                queue = JobQueue::new(
+                    config.max_concurrent_jobs,
+                    config.queue_capacity,
+                    pending.clone(),
+                );
+            }
+        }
+
+        match tokio::time::timeout(Duration::from_millis(100), rx.recv()).await {
+            Ok(Some(event)) => {
+                let Ok(Event { kind, paths, attrs }) = event else {
+                    continue;
+                };
+                if kind == EventKind::Modify(ModifyKind::Name(RenameMode::From)) {
+                    for path in &paths {
+                        intake.note_move_out(attrs.tracker(), path);
+                    }
+                    continue;
+                }
+                let created = matches!(kind, EventKind::Create(_));
+                // finished downloads are renamed into place, not created
+                let rename = match kind {
+                    EventKind::Modify(ModifyKind::Name(mode)) => Some(mode),
+                    _ => None,
+                };
+                if !created && rename.is_none() {
+                    continue;
+                }
+                // with both sides known, the first path is the old name
+                let (source, paths) = match (rename, paths.as_slice()) {
+                    (Some(RenameMode::Both), [source, target]) => {
+                        (Some(source.clone()), vec![target.clone()])
+                    }
+                    _ => (None, paths),
+                };
+
+                // named with the frontmost window, looked up once per event
+                let mut screenshots = Vec::new();
+                for path in paths {
+                    // the most specific folder wins when watches overlap
+                    let Some(folder) = owning_folder(&active, &path) else {
+                        continue;
+                    };
+                    if !folder.rule.screenshots_only && is_partial_download(&path) {
+                        intake.note_partial(&path);
+                        continue;
+                    }
+                    if !folder.matches(&path) {
+                        continue;
+                    }
+                    if let Some(mode) = rename {
+                        if folder.rule.screenshots_only
+                            || !intake.accepts(
+                                mode,
+                                attrs.tracker(),
+                                source.as_deref(),
+                                &path,
+                                &folder.root,
+                            )
+                        {
+                            continue;
+                        }
+                    }
+
+                    let address = config.get_config_address();
+
+                    // skip files we can't handle and our own renames early,
+                    // so they never reach the queue or the pending list
+                    if folder.rule.screenshots_only {
+                        if !folder.manager.is_screenshot_file(&path) {
+                            continue;
                         }
+                        // persist the final name, the hidden one is short lived
+                        let path = folder.manager.modify_ss_path(&path);
+                        if journal.is_processed(&path) {
+                            continue;
+                        }
+
+                        info!("Detected new screenshot: {:?}", path);
+                        screenshots.push((path, folder.manager.clone()));
+                        continue;
                     }
+
+                    if !path.is_file() || journal.is_processed(&path) {
+                        continue;
+                    }
+                    info!("Detected new file: {:?}", path);
+                    // the frontmost app says nothing about a download
+                    let context = NamingContext::new(address);
+                    // renaming a file that is still being written breaks the
+                    // writer, so only queue it once its size settles
+                    let queue = queue.clone();
+                    let journal = journal.clone();
+                    let manager = folder.manager.clone();
+                    tokio::spawn(async move {
+                        if !wait_until_settled(&path).await || journal.is_processed(&path) {
+                            return;
+                        }
+                        queue
+                            .submit(Job {
+                                path,
+                                context,
+                                manager,
+                                screenshot: false,
+                            })
+                            .await;
+                    });
                 }
+
+                if !screenshots.is_empty() {
+                    // capture the frontmost window before it changes. osascript
+                    // takes a while, so the intake loop doesn't wait for it,
+                    // only the jobs that need it do
+                    let lookup = tokio::task::spawn_blocking(macos::get_frontmost_window);
+                    let address = config.get_config_address();
+                    let queue = queue.clone();
+                    tokio::spawn(async move {
+                        let frontmost = lookup.await.unwrap_or_default();
+                        for (path, manager) in screenshots {
+                            let context = NamingContext {
+                                address: address.clone(),
+                                frontmost: frontmost.clone(),
+                                screenshot: true,
+                            };
+                            queue
+                                .submit(Job {
+                                    path,
+                                    context,
+                                    manager,
+                                    screenshot: true,
+                                })
+                                .await;
+                        }
+                    });
+                }
+            }
+            Ok(None) => {
+                error!("Watch channel closed");
+                break;
             }
-            Err(RecvTimeoutError::Timeout) => continue,
-            Err(e) => error!("Watch error: {:?}", e),
+            Err(_) => continue,
         }
     }
 
     info!("Shutting down Goggles thread...");
-    watcher.unwatch(&screenshot_dir).ok();
+    for folder in &active {
+        watcher.unwatch(&folder.root).ok();
+    }
 }
 
-pub async fn run() {
-    let new_pid = std::process::id();
-    info!("Starting Goggles daemon with PID {}", new_pid);
-
-    // save the pid
-    pid::save_pid(new_pid);
+pub async fn run_with_options(options: DaemonOptions) {
+    info!("Starting Goggles daemon with PID {}", std::process::id());
 
     let shutdown = Arc::new(AtomicBool::new(false));
     let shutdown_clone = shutdown.clone();
+    let config_rx = spawn_config_manager();
 
     let goggles_thread_handler = tokio::spawn(async move {
         info!("Starting Goggles thread...");
-        daemon(shutdown_clone).await;
+        daemon(shutdown_clone, config_rx, backend_from_config, options).await;
     });
 
     // Wait for shutdown signal
@@ -87,3 +626,220 @@ pub async fn run() {
     tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
     info!("Goggles: Shutting down");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_partial_downloads() {
+        for name in [
+            "report.pdf.crdownload",
+            "video.mp4.part",
+            "a.DOWNLOAD",
+            "x.tmp",
+        ] {
+            assert!(is_partial_download(Path::new(name)), "{}", name);
+        }
+        for name in ["report.pdf", "part", "notes.partition"] {
+            assert!(!is_partial_download(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn leaves_renames_inside_the_folder_alone() {
+        let root = Path::new("/watched");
+        let mut intake = RenameIntake::default();
+
+        let renamed = root.join("fixed-name.png");
+        let source = root.join("bad-ai-name.png");
+        assert!(!intake.accepts(RenameMode::Both, None, Some(&source), &renamed, root));
+        // an editor saving through a temp file
+        let saved = root.join("notes.txt");
+        let temp = root.join(".notes.txt.swp");
+        assert!(!intake.accepts(RenameMode::Both, None, Some(&temp), &saved, root));
+        // the watcher couldn't tell where it came from
+        assert!(!intake.accepts(RenameMode::Any, None, None, &renamed, root));
+    }
+
+    #[test]
+    fn picks_up_completed_downloads_and_moved_in_files() {
+        let root = Path::new("/watched");
+        let mut intake = RenameIntake::default();
+        let report = root.join("report.pdf");
+
+        let partial = root.join("report.pdf.crdownload");
+        assert!(intake.accepts(RenameMode::Both, None, Some(&partial), &report, root));
+
+        // FSEvents reports each side on its own
+        intake.note_partial(&root.join("report.pdf.part"));
+        assert!(intake.accepts(RenameMode::Any, None, None, &report, root));
+        assert!(!intake.accepts(RenameMode::Any, None, None, &report, root));
+
+        let elsewhere = Path::new("/elsewhere/report.pdf");
+        assert!(intake.accepts(RenameMode::Both, None, Some(elsewhere), &report, root));
+        assert!(intake.accepts(RenameMode::To, None, None, &report, root));
+    }
+
+    #[test]
+    fn pairs_the_separate_sides_inotify_reports() {
+        let root = Path::new("/watched");
+        let mut intake = RenameIntake::default();
+
+        // inotify sends From, then To, then Both with the same cookie
+        let source = root.join("bad-ai-name.png");
+        let renamed = root.join("fixed-name.png");
+        intake.note_move_out(Some(7), &source);
+        assert!(!intake.accepts(RenameMode::To, Some(7), None, &renamed, root));
+        assert!(!intake.accepts(RenameMode::Both, Some(7), Some(&source), &renamed, root));
+
+        let temp = root.join(".notes.txt.swp");
+        let saved = root.join("notes.txt");
+        intake.note_move_out(Some(8), &temp);
+        assert!(!intake.accepts(RenameMode::To, Some(8), None, &saved, root));
+        assert!(!intake.accepts(RenameMode::Both, Some(8), Some(&temp), &saved, root));
+
+        // a completed download is taken once, from the Both event
+        let partial = root.join("report.pdf.crdownload");
+        let report = root.join("report.pdf");
+        intake.note_partial(&partial);
+        intake.note_move_out(Some(9), &partial);
+        assert!(!intake.accepts(RenameMode::To, Some(9), None, &report, root));
+        assert!(intake.accepts(RenameMode::Both, Some(9), Some(&partial), &report, root));
+
+        // a cookie with no old side in any watched folder
+        assert!(intake.accepts(RenameMode::To, Some(10), None, &report, root));
+        assert!(intake.moves_out.is_empty());
+    }
+
+    #[test]
+    fn pairs_untracked_sides_without_a_both_event() {
+        let root = Path::new("/watched");
+        let mut intake = RenameIntake::default();
+
+        // Windows sends From and To without a tracker, and no Both
+        let source = root.join("bad-ai-name.png");
+        let renamed = root.join("fixed-name.png");
+        intake.note_move_out(None, &source);
+        assert!(!intake.accepts(RenameMode::To, None, None, &renamed, root));
+
+        let partial = root.join("report.pdf.part");
+        let report = root.join("report.pdf");
+        intake.note_move_out(None, &partial);
+        assert!(intake.accepts(RenameMode::To, None, None, &report, root));
+    }
+
+    #[tokio::test]
+    async fn resumes_pending_jobs_on_startup() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("watched");
+        std::fs::create_dir(&root).unwrap();
+        let shot = root.join("image.png");
+        image::RgbImage::new(8, 8).save(&shot).unwrap();
+        let outside = dir.path().join("outside.png");
+
+        let pending = PendingJobs::new(dir.path().join("pending.json"));
+        pending.add(&shot, false).unwrap();
+        pending.add(&outside, false).unwrap();
+
+        let config = GogglesConfig::default();
+        let rule = WatchedFolder {
+            path: root.clone(),
+            recursive: false,
+            filters: vec![],
+            template: None,
+            enabled: true,
+            screenshots_only: false,
+            never_upload: true,
+        };
+        let backend: Arc<dyn NamingBackend> = Arc::new(LocalBackend::new());
+        let journal = RenameJournal::new(dir.path().join("journal.jsonl"));
+        let active = vec![ActiveFolder {
+            manager: build_manager(&backend, &journal, &config, &rule, false),
+            rule,
+            root,
+            filters: vec![],
+        }];
+        let queue = JobQueue::new(1, 10, Some(pending.clone()));
+
+        resume_pending(&queue, &pending, &active, &config).await;
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !pending.list().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("resumed job never finished");
+        assert!(!shot.exists());
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].original_path, shot);
+    }
+
+    #[tokio::test]
+    async fn never_upload_folders_are_named_locally() {
+        use crate::watcher::{
+            ai::OpenAI, mock_server::mock_server, naming::FallbackBackend, retry::CircuitBreaker,
+        };
+        static CIRCUIT: CircuitBreaker = CircuitBreaker::new(10, Duration::from_secs(60));
+
+        let (url, requests) = mock_server(vec![(500, "{}")]);
+        let dir = tempfile::tempdir().unwrap();
+        let backend: Arc<dyn NamingBackend> = Arc::new(FallbackBackend::new(
+            Arc::new(OpenAI::for_test(&url, &CIRCUIT)),
+            Arc::new(LocalBackend::new()),
+        ));
+        let journal = RenameJournal::new(dir.path().join("journal.jsonl"));
+        let rule = WatchedFolder {
+            path: dir.path().to_path_buf(),
+            recursive: false,
+            filters: vec![],
+            template: None,
+            enabled: true,
+            screenshots_only: false,
+            never_upload: true,
+        };
+        let manager = build_manager(&backend, &journal, &GogglesConfig::default(), &rule, false);
+
+        let path = dir.path().join("image.png");
+        image::RgbImage::new(8, 8).save(&path).unwrap();
+        let new_path = manager
+            .process_new_file(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+
+        assert!(new_path.exists());
+        assert_eq!(journal.entries().unwrap()[0].backend, "local");
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn settles_once_the_file_stops_growing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("download.bin");
+        std::fs::write(&path, b"first").unwrap();
+
+        let writer = {
+            let path = path.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(SETTLE_POLL / 2).await;
+                std::fs::write(&path, b"first and second").unwrap();
+            })
+        };
+        assert!(wait_until_settled(&path).await);
+        writer.await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"first and second");
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_missing_files_directories_and_placeholders() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!wait_until_settled(&dir.path().join("gone.bin")).await);
+        assert!(!wait_until_settled(dir.path()).await);
+
+        let placeholder = dir.path().join("report.pdf");
+        std::fs::write(&placeholder, b"").unwrap();
+        assert!(!wait_until_settled(&placeholder).await);
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Errors the UI needs to tell apart. The pipeline passes them around
+/// inside `anyhow::Error`, `from_anyhow` recovers them at the edge.
+#[derive(Debug, thiserror::Error)]
+pub enum GogglesError {
+    #[error("Network error: {0}")]
+    Network(#[source] reqwest::Error),
+    #[error("Server returned {status}: {message}")]
+    Server {
+        status: u16,
+        message: String,
+        details: Option<String>,
+    },
+    #[error("Insufficient credits")]
+    InsufficientCredits { details: Option<String> },
+    #[error("Server unavailable, retrying in {retry_in_secs}s")]
+    ServerUnavailable { retry_in_secs: u64 },
+    #[error("Invalid server response: {0}")]
+    InvalidResponse(String),
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+    #[error("File already exists: {}", .0.display())]
+    AlreadyExists(PathBuf),
+    #[error("Not a screenshot: {}", .0.display())]
+    NotAScreenshot(PathBuf),
+    #[error("Not an image: {}", .0.display())]
+    NotAnImage(PathBuf),
+    #[error("Already renamed: {}", .0.display())]
+    AlreadyRenamed(PathBuf),
+    #[error("Kept on device by privacy rule {rule:?}: {}", .path.display())]
+    Private { path: PathBuf, rule: String },
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Config error: {0}")]
+    Config(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl GogglesError {
+    /// Stable identifier the frontend matches on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GogglesError::Network(_) => "network",
+            GogglesError::Server { .. } => "server",
+            GogglesError::InsufficientCredits { .. } => "insufficient_credits",
+            GogglesError::ServerUnavailable { .. } => "server_unavailable",
+            GogglesError::InvalidResponse(_) => "invalid_response",
+            GogglesError::InvalidName(_) => "invalid_name",
+            GogglesError::AlreadyExists(_) => "already_exists",
+            GogglesError::NotAScreenshot(_) => "not_a_screenshot",
+            GogglesError::NotAnImage(_) => "not_an_image",
+            GogglesError::AlreadyRenamed(_) => "already_renamed",
+            GogglesError::Private { .. } => "private",
+            GogglesError::Io(_) => "io",
+            GogglesError::Config(_) => "config",
+            GogglesError::Other(_) => "other",
+        }
+    }
+
+    /// Extra explanation sent by the server, if any.
+    pub fn details(&self) -> Option<&str> {
+        match self {
+            GogglesError::Server { details, .. }
+            | GogglesError::InsufficientCredits { details } => details.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Digs a typed error out of `error`, anything unknown becomes `Other`.
+    /// I/O errors keep their kind and the context added on the way up.
+    pub fn from_anyhow(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<GogglesError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        match error.downcast_ref::<std::io::Error>() {
+            Some(io) => GogglesError::Io(std::io::Error::new(io.kind(), format!("{:#}", error))),
+            None => GogglesError::Other(format!("{:#}", error)),
+        }
+    }
+
+    /// Wraps a config load or save failure.
+    #[cfg(feature = "gui")]
+    pub fn config(error: impl std::fmt::Display) -> Self {
+        GogglesError::Config(error.to_string())
+    }
+}
+
+impl From<anyhow::Error> for GogglesError {
+    fn from(error: anyhow::Error) -> Self {
+        GogglesError::from_anyhow(error)
+    }
+}
+
+// sent to the frontend as `{ kind, message, ...fields }`
+impl Serialize for GogglesError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("GogglesError", 5)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        if let Some(details) = self.details() {
+            state.serialize_field("details", details)?;
+        }
+        match self {
+            GogglesError::Server { status, .. } => state.serialize_field("status", status)?,
+            GogglesError::ServerUnavailable { retry_in_secs } => {
+                state.serialize_field("retry_in_secs", retry_in_secs)?
+            }
+            GogglesError::AlreadyExists(path)
+            | GogglesError::NotAScreenshot(path)
+            | GogglesError::NotAnImage(path)
+            | GogglesError::AlreadyRenamed(path) => state.serialize_field("path", path)?,
+            GogglesError::Private { path, rule } => {
+                state.serialize_field("path", path)?;
+                state.serialize_field("rule", rule)?;
+            }
+            _ => {}
+        }
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn io_errors_with_context_keep_their_kind() {
+        let error = anyhow::Error::new(Error::from(ErrorKind::PermissionDenied))
+            .context("Failed to rename file: \"a.png\" -> \"b.png\"");
+
+        let error = GogglesError::from_anyhow(error);
+        assert!(matches!(&error, GogglesError::Io(e) if e.kind() == ErrorKind::PermissionDenied));
+
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["kind"], "io");
+        assert!(json["message"]
+            .as_str()
+            .unwrap()
+            .contains("Failed to rename file"));
+    }
+}
@@ -0,0 +1,373 @@
+use std::fs::{self, File, FileTimes};
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+
+use crate::watcher::error::GogglesError;
+
+/// Moves `from` to `to`, keeping timestamps, permissions and extended
+/// attributes. Uses a plain rename on the same filesystem and falls back to
+/// copy, fsync and delete across devices.
+///
+/// Never replaces an existing `to`, even one another process created after
+/// the name was picked, and fails with `AlreadyExists` instead.
+pub fn move_file(from: &Path, to: &Path) -> Result<(), anyhow::Error> {
+    match rename_no_replace(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            Err(GogglesError::AlreadyExists(to.to_path_buf()).into())
+        }
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => copy_and_delete(from, to),
+        Err(e) => {
+            Err(anyhow::Error::new(e)
+                .context(format!("Failed to rename file: {:?} -> {:?}", from, to)))
+        }
+    }
+}
+
+fn copy_and_delete(from: &Path, to: &Path) -> Result<(), anyhow::Error> {
+    let metadata = fs::metadata(from)?;
+
+    // claim the name first, fs::copy would replace a file created meanwhile
+    if let Err(e) = File::options().write(true).create_new(true).open(to) {
+        if e.kind() == ErrorKind::AlreadyExists {
+            return Err(GogglesError::AlreadyExists(to.to_path_buf()).into());
+        }
+        return Err(anyhow::Error::new(e).context(format!("Failed to create file: {:?}", to)));
+    }
+
+    if let Err(e) = copy_synced(from, to, &metadata) {
+        // a partial or unsynced copy would leave two files behind
+        let _ = fs::remove_file(to);
+        return Err(
+            anyhow::Error::new(e).context(format!("Failed to copy file: {:?} -> {:?}", from, to))
+        );
+    }
+
+    if let Err(e) = fs::remove_file(from) {
+        // don't leave two copies behind
+        let _ = fs::remove_file(to);
+        return Err(anyhow::Error::new(e).context(format!("Failed to delete file: {:?}", from)));
+    }
+    Ok(())
+}
+
+/// Copies `from` to `to` and only returns once the copy is on disk.
+fn copy_synced(from: &Path, to: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    // fs::copy carries permissions over (and xattrs on macOS)
+    fs::copy(from, to)?;
+    let copied = File::options().write(true).open(to)?;
+    copied.set_times(file_times(metadata))?;
+    copied.sync_all()
+}
+
+#[cfg(target_os = "linux")]
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let from_c = CString::new(from.as_os_str().as_bytes())?;
+    let to_c = CString::new(to.as_os_str().as_bytes())?;
+    // SAFETY: both paths are valid NUL terminated strings
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            from_c.as_ptr(),
+            libc::AT_FDCWD,
+            to_c.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        // filesystems or kernels without RENAME_NOREPLACE
+        Some(libc::EINVAL | libc::ENOSYS | libc::EOPNOTSUPP) => link_and_unlink(from, to),
+        _ => Err(error),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let from_c = CString::new(from.as_os_str().as_bytes())?;
+    let to_c = CString::new(to.as_os_str().as_bytes())?;
+    // SAFETY: both paths are valid NUL terminated strings
+    let result = unsafe { libc::renamex_np(from_c.as_ptr(), to_c.as_ptr(), libc::RENAME_EXCL) };
+    if result == 0 {
+        return Ok(());
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        // filesystems without RENAME_EXCL
+        Some(libc::EINVAL | libc::ENOTSUP) => link_and_unlink(from, to),
+        _ => Err(error),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    link_and_unlink(from, to)
+}
+
+/// No-replace rename built from a hard link, which never overwrites.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn link_and_unlink(from: &Path, to: &Path) -> io::Result<()> {
+    fs::hard_link(from, to)?;
+    if let Err(e) = fs::remove_file(from) {
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Replaces `path` with `bytes` through a synced temp file, so a failed
+/// or interrupted write leaves the old file intact. The new file keeps the
+/// permissions of the one it replaces, or is only readable by the owner.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = create_temp(path, &temp_path).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    sync_parent(path)
+}
+
+#[cfg(unix)]
+fn create_temp(path: &Path, temp_path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mode = match fs::metadata(path) {
+        Ok(metadata) => metadata.permissions().mode() & 0o7777,
+        Err(e) if e.kind() == ErrorKind::NotFound => 0o600,
+        Err(e) => return Err(e),
+    };
+    let file = File::options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(temp_path)?;
+    // the umask narrows `mode`, and a stale temp file keeps its own
+    file.set_permissions(fs::Permissions::from_mode(mode))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_temp(path: &Path, temp_path: &Path) -> io::Result<File> {
+    let file = File::create(temp_path)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    Ok(file)
+}
+
+/// Makes the rename into `path` durable, not only the file contents.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+/// Directories can't be opened for syncing here, the rename is as durable
+/// as the platform makes it.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn file_times(metadata: &fs::Metadata) -> FileTimes {
+    let mut times = FileTimes::new();
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    #[cfg(target_os = "macos")]
+    if let Ok(created) = metadata.created() {
+        use std::os::macos::fs::FileTimesExt;
+        times = times.set_created(created);
+    }
+    times
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn old_file(dir: &Path) -> (std::path::PathBuf, SystemTime) {
+        let path = dir.join("original.png");
+        fs::write(&path, b"image").unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(86_400);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        (path, modified)
+    }
+
+    #[test]
+    fn renames_on_the_same_device() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, modified) = old_file(dir.path());
+        let to = dir.path().join("renamed.png");
+
+        move_file(&from, &to).unwrap();
+
+        assert!(!from.exists());
+        assert_eq!(fs::read(&to).unwrap(), b"image");
+        assert_eq!(fs::metadata(&to).unwrap().modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn cross_device_fallback_keeps_timestamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, modified) = old_file(dir.path());
+        let to = dir.path().join("copied.png");
+
+        copy_and_delete(&from, &to).unwrap();
+
+        assert!(!from.exists());
+        assert_eq!(fs::read(&to).unwrap(), b"image");
+        assert_eq!(fs::metadata(&to).unwrap().modified().unwrap(), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cross_device_fallback_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (from, _) = old_file(dir.path());
+        fs::set_permissions(&from, fs::Permissions::from_mode(0o640)).unwrap();
+        let to = dir.path().join("copied.png");
+
+        copy_and_delete(&from, &to).unwrap();
+
+        let mode = fs::metadata(&to).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn never_replaces_an_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, _) = old_file(dir.path());
+        let to = dir.path().join("taken.png");
+        fs::write(&to, b"other").unwrap();
+
+        let error = move_file(&from, &to).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<GogglesError>(),
+            Some(GogglesError::AlreadyExists(_))
+        ));
+        assert!(copy_and_delete(&from, &to).is_err());
+
+        assert_eq!(fs::read(&from).unwrap(), b"image");
+        assert_eq!(fs::read(&to).unwrap(), b"other");
+    }
+
+    #[test]
+    fn failed_copy_keeps_only_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, _) = old_file(dir.path());
+        let to = dir.path().join("missing").join("copied.png");
+
+        assert!(copy_and_delete(&from, &to).is_err());
+
+        assert!(from.exists());
+        assert!(!to.exists());
+    }
+
+    fn serialized_kind(error: anyhow::Error) -> serde_json::Value {
+        serde_json::to_value(GogglesError::from(error)).unwrap()["kind"].clone()
+    }
+
+    #[test]
+    fn failed_moves_keep_the_io_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = move_file(&dir.path().join("gone.png"), &dir.path().join("b.png")).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<io::Error>().map(io::Error::kind),
+            Some(ErrorKind::NotFound)
+        );
+        assert_eq!(serialized_kind(error), "io");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permission_errors_reach_the_ui_as_io() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // root ignores directory permissions
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let (from, _) = old_file(dir.path());
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o500)).unwrap();
+
+        let error = move_file(&from, &dir.path().join("renamed.png")).unwrap_err();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o700)).unwrap();
+
+        let error = GogglesError::from(error);
+        assert!(
+            matches!(&error, GogglesError::Io(e) if e.kind() == ErrorKind::PermissionDenied),
+            "{:?}",
+            error
+        );
+        assert_eq!(serde_json::to_value(&error).unwrap()["kind"], "io");
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file_and_leaves_no_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, b"old").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!dir.path().join("state.json.tmp").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_keeps_the_permissions_of_the_replaced_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let shared = dir.path().join("shared.json");
+        fs::write(&shared, b"old").unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o644)).unwrap();
+        // a stale temp file must not lend its mode either
+        let stale = dir.path().join("shared.json.tmp");
+        fs::write(&stale, b"stale").unwrap();
+        fs::set_permissions(&stale, fs::Permissions::from_mode(0o666)).unwrap();
+
+        write_atomic(&shared, b"new").unwrap();
+        let mode = fs::metadata(&shared).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+
+        let fresh = dir.path().join("fresh.json");
+        write_atomic(&fresh, b"new").unwrap();
+        let mode = fs::metadata(&fresh).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::time::Duration;
+
+use crate::watcher::config::GogglesConfig;
+use crate::watcher::error::GogglesError;
+use crate::watcher::utils::expand_home;
+
+/// Whole-request timeout when `server_timeout_secs` is not set.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest wait for the next chunk of a response.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// The long-lived client for the naming server, built once per config.
+pub fn build_client(config: &GogglesConfig) -> Result<reqwest::Client, GogglesError> {
+    let request_timeout = config
+        .server_timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT);
+
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.server_connect_timeout_secs))
+        .read_timeout(READ_TIMEOUT)
+        .timeout(request_timeout)
+        .tcp_keepalive(KEEP_ALIVE_INTERVAL)
+        .http2_keep_alive_interval(KEEP_ALIVE_INTERVAL)
+        .http2_keep_alive_while_idle(true)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT);
+
+    if let Some(proxy) = &config.server_proxy {
+        let proxy = reqwest::Proxy::all(proxy).map_err(|e| {
+            GogglesError::Config(format!("Invalid server_proxy {:?}: {}", proxy, e))
+        })?;
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = &config.server_ca_bundle {
+        let path = expand_home(path);
+        let pem = fs::read(&path).map_err(|e| {
+            GogglesError::Config(format!("Failed to read server_ca_bundle {:?}: {}", path, e))
+        })?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| {
+            GogglesError::Config(format!("Invalid server_ca_bundle {:?}: {}", path, e))
+        })?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder
+        .build()
+        .map_err(|e| GogglesError::Config(format!("Failed to build HTTP client: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_error(config: GogglesConfig) -> String {
+        match build_client(&config) {
+            Err(GogglesError::Config(message)) => message,
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn builds_a_client_from_the_defaults() {
+        assert!(build_client(&GogglesConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn rejects_an_invalid_proxy() {
+        let message = config_error(GogglesConfig {
+            server_proxy: Some("http://[not a host".to_string()),
+            ..GogglesConfig::default()
+        });
+        assert!(message.contains("server_proxy"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_an_unreadable_ca_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let message = config_error(GogglesConfig {
+            server_ca_bundle: Some(dir.path().join("missing.pem")),
+            ..GogglesConfig::default()
+        });
+        assert!(
+            message.contains("Failed to read server_ca_bundle"),
+            "{}",
+            message
+        );
+
+        let garbage = dir.path().join("garbage.pem");
+        fs::write(&garbage, "-----BEGIN CERTIFICATE-----\nnot base64\n").unwrap();
+        let message = config_error(GogglesConfig {
+            server_ca_bundle: Some(garbage),
+            ..GogglesConfig::default()
+        });
+        assert!(message.contains("server_ca_bundle"), "{}", message);
+    }
+}
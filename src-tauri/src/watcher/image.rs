@@ -1,28 +1,343 @@
-use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime};
+use std::sync::Arc;
 
-use crate::watcher::ai::OpenAI;
+use log::{error, info};
 
-#[derive(Debug, Clone)]
+use crate::watcher::collision::{resolve_collision, CollisionPolicy};
+use crate::watcher::config::GogglesConfig;
+use crate::watcher::error::GogglesError;
+use crate::watcher::fsops::move_file;
+use crate::watcher::journal::RenameJournal;
+use crate::watcher::naming::{backend_from_config, NameSuggestion, NamingBackend, NamingContext};
+#[cfg(feature = "gui")]
+use crate::watcher::plan::RenamePlan;
+use crate::watcher::sanitize::sanitize_filename;
+use crate::watcher::template::{NameTemplate, TemplateValues};
+
+/// Upper bound for `{counter}` before giving up on finding a free name.
+const MAX_COUNTER: u32 = 10_000;
+/// Times a name is picked again when another process took it first.
+const MAX_MOVE_ATTEMPTS: u32 = 5;
+
+// picking a free name and moving the file must not interleave between
+// concurrent renames, or two files could claim the same name. Other
+// processes aren't covered, `move_file` never replaces their files.
+// Async, so waiting renames don't block runtime threads.
+static RENAME_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Runs `work` on a blocking thread while holding `RENAME_LOCK`, moves
+/// across devices copy the whole file.
+async fn with_rename_lock<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, anyhow::Error> + Send + 'static,
+) -> Result<T, anyhow::Error> {
+    let _guard = RENAME_LOCK.lock().await;
+    tokio::task::spawn_blocking(work).await?
+}
+
+#[derive(Clone)]
 pub struct SSManager {
-    ai: OpenAI,
+    backend: Arc<dyn NamingBackend>,
+    journal: RenameJournal,
+    collision_policy: CollisionPolicy,
+    template: NameTemplate,
+    dry_run: bool,
 }
 
 impl SSManager {
-    pub fn new(ai: OpenAI) -> Self {
-        Self { ai }
+    pub fn new(backend: Arc<dyn NamingBackend>, journal: RenameJournal) -> Self {
+        Self {
+            backend,
+            journal,
+            collision_policy: CollisionPolicy::default(),
+            template: NameTemplate::default(),
+            dry_run: false,
+        }
+    }
+
+    /// A manager using the global backend, template and collision policy.
+    pub fn from_config(
+        config: &GogglesConfig,
+        journal: RenameJournal,
+    ) -> Result<Self, anyhow::Error> {
+        Self::from_config_with_backend(config, backend_from_config(config)?, journal)
+    }
+
+    /// Like `from_config`, reusing an already built backend.
+    pub fn from_config_with_backend(
+        config: &GogglesConfig,
+        backend: Arc<dyn NamingBackend>,
+        journal: RenameJournal,
+    ) -> Result<Self, anyhow::Error> {
+        let template = NameTemplate::parse(&config.name_template)
+            .map_err(|e| GogglesError::Config(format!("Invalid name template: {}", e)))?;
+        Ok(Self::new(backend, journal)
+            .with_collision_policy(config.collision_policy)
+            .with_template(template))
+    }
+
+    pub fn with_backend(mut self, backend: Arc<dyn NamingBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_template(mut self, template: NameTemplate) -> Self {
+        self.template = template;
+        self
+    }
+
+    pub fn with_collision_policy(mut self, collision_policy: CollisionPolicy) -> Self {
+        self.collision_policy = collision_policy;
+        self
+    }
+
+    /// Logs the rename instead of moving the file or touching the journal.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn journal(&self) -> &RenameJournal {
+        &self.journal
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Asks the backend for a name (when the template needs one) and
+    /// renders the template. The returned suggestion holds the final stem.
+    async fn get_name(
+        &self,
+        context: &NamingContext,
+        path: &Path,
+    ) -> Result<(NameSuggestion, TemplateValues), anyhow::Error> {
+        let (ai, backend) = if self.template.needs_ai() {
+            let suggestion = self.backend.suggest_name(path, context).await?;
+            (
+                Some(sanitize_filename(&suggestion.name)?),
+                suggestion.backend,
+            )
+        } else {
+            (None, "template".to_string())
+        };
+
+        let values = TemplateValues::collect(path, context, ai);
+        let name = sanitize_filename(&self.template.render(&values, 1))?;
+        Ok((NameSuggestion { name, backend }, values))
     }
 
-    fn modify_ss_path(&self, path: &PathBuf) -> PathBuf {
+    /// Picks the destination for `path`, honouring `{counter}` and the
+    /// collision policy. `path` itself when it already has the name.
+    fn new_path(
+        &self,
+        path: &Path,
+        suggestion: &NameSuggestion,
+        values: &TemplateValues,
+        extension: &str,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let parent = path.parent().unwrap_or(Path::new("."));
+
+        if self.template.has_counter() {
+            for counter in 1..=MAX_COUNTER {
+                let stem = sanitize_filename(&self.template.render(values, counter))?;
+                let candidate = parent.join(format!("{}.{}", stem, extension));
+                if candidate == path || !candidate.exists() {
+                    return Ok(candidate);
+                }
+            }
+            return Err(GogglesError::AlreadyExists(
+                parent.join(format!("{}.{}", suggestion.name, extension)),
+            )
+            .into());
+        }
+
+        let new_path = parent.join(format!("{}.{}", suggestion.name, extension));
+        if new_path == path {
+            return Ok(new_path);
+        }
+        match resolve_collision(&new_path, self.collision_policy) {
+            Some(path) => Ok(path),
+            None => Err(GogglesError::AlreadyExists(new_path).into()),
+        }
+    }
+
+    /// Moves `path` to a free name built from `suggestion` and journals it.
+    async fn rename(
+        &self,
+        context: &NamingContext,
+        path: &Path,
+        suggestion: &NameSuggestion,
+        values: &TemplateValues,
+        extension: &str,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let manager = self.clone();
+        let context = context.clone();
+        let path = path.to_path_buf();
+        let suggestion = suggestion.clone();
+        let values = values.clone();
+        let extension = extension.to_string();
+        with_rename_lock(move || {
+            manager.rename_blocking(&context, &path, &suggestion, &values, &extension)
+        })
+        .await
+    }
+
+    fn rename_blocking(
+        &self,
+        context: &NamingContext,
+        path: &Path,
+        suggestion: &NameSuggestion,
+        values: &TemplateValues,
+        extension: &str,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let mut attempts = 1;
+        loop {
+            let new_path = self.new_path(path, suggestion, values, extension)?;
+            if new_path == path {
+                info!("{:?} already has the suggested name", path);
+                return Ok(new_path);
+            }
+
+            if self.dry_run {
+                info!("Dry run, would rename {:?} to {:?}", path, new_path);
+                return Ok(new_path);
+            }
+
+            match move_file(path, &new_path) {
+                Ok(()) => {
+                    self.record_rename(context, suggestion, path, &new_path);
+                    return Ok(new_path);
+                }
+                Err(e) if attempts < MAX_MOVE_ATTEMPTS && is_already_exists(&e) => {
+                    info!("{:?} was taken meanwhile, picking another name", new_path);
+                    attempts += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Works out the new name for `path` without moving anything.
+    #[cfg(feature = "gui")]
+    pub async fn plan_rename(
+        &self,
+        context: &NamingContext,
+        path: &Path,
+    ) -> Result<RenamePlan, anyhow::Error> {
+        let extension = file_extension(path)?;
+        let (suggestion, values) = self.get_name(context, path).await?;
+
+        let parent = path.parent().unwrap_or(Path::new("."));
+        let wanted = parent.join(format!("{}.{}", suggestion.name, extension));
+        let collision = (wanted.exists() && wanted != path).then_some(wanted);
+
+        let (new_path, reason) = match self.new_path(path, &suggestion, &values, extension) {
+            Ok(new_path) if new_path == path => {
+                (None, format!("Already named {}", display_name(path)))
+            }
+            Ok(new_path) => {
+                let reason = match &collision {
+                    Some(taken) => format!(
+                        "{} already exists, using {}",
+                        display_name(taken),
+                        display_name(&new_path)
+                    ),
+                    None => format!("Named by {}", suggestion.backend),
+                };
+                (Some(new_path), reason)
+            }
+            Err(e) => (None, e.to_string()),
+        };
+
+        Ok(RenamePlan {
+            old_path: path.to_path_buf(),
+            new_path,
+            collision,
+            backend: suggestion.backend,
+            reason,
+        })
+    }
+
+    /// Carries out a plan from `plan_rename`, optionally with a name the
+    /// user typed instead of the suggested one.
+    #[cfg(feature = "gui")]
+    pub async fn apply_plan(
+        &self,
+        context: &NamingContext,
+        plan: &RenamePlan,
+        name: Option<&str>,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let path = plan.old_path.as_path();
+        if !path.exists() {
+            return Err(GogglesError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("File no longer exists: {:?}", path),
+            ))
+            .into());
+        }
+
+        let (stem, backend) = match name {
+            Some(name) => (sanitize_filename(name)?, "manual".to_string()),
+            None => {
+                let new_path = plan
+                    .new_path
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("Nothing to apply: {}", plan.reason))?;
+                let stem = new_path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .ok_or_else(|| {
+                        GogglesError::InvalidName(format!("Invalid planned path: {:?}", new_path))
+                    })?;
+                // the plan comes back from the UI, never trust it with a path
+                (sanitize_filename(stem)?, plan.backend.clone())
+            }
+        };
+
+        let extension = file_extension(path)?;
+        let values = TemplateValues::collect(path, context, Some(stem.clone()));
+        let suggestion = NameSuggestion {
+            name: stem,
+            backend,
+        };
+
+        // the stem is final, a `{counter}` template must not render it again
+        let manager = self.clone().with_template(NameTemplate::default());
+        let context = context.clone();
+        let path = path.to_path_buf();
+        let extension = extension.to_string();
+        with_rename_lock(move || {
+            manager.rename_blocking(&context, &path, &suggestion, &values, &extension)
+        })
+        .await
+    }
+
+    fn record_rename(
+        &self,
+        context: &NamingContext,
+        suggestion: &NameSuggestion,
+        old_path: &Path,
+        new_path: &Path,
+    ) {
+        // the file is already renamed, a journal failure must not fail the job
+        if let Err(e) =
+            self.journal
+                .record(old_path, new_path, &suggestion.backend, &context.address)
+        {
+            error!("Failed to record rename in journal: {:?}", e);
+        }
+    }
+
+    pub fn modify_ss_path(&self, path: &Path) -> PathBuf {
         // initially the path of the file starts with .<file_name>
         // we need to remove the . from the file name
-        let filename = path.file_name().unwrap().to_str().unwrap()[1..].to_string();
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        let filename = filename.strip_prefix('.').unwrap_or(filename);
         let parent = path.parent().unwrap_or(Path::new("."));
         parent.join(filename)
     }
 
-    pub fn is_screenshot_file(&self, path: &PathBuf) -> bool {
+    pub fn is_screenshot_file(&self, path: &Path) -> bool {
         if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
             let mut lowercase = filename.to_lowercase();
             if lowercase.starts_with(".") {
@@ -30,108 +345,423 @@ impl SSManager {
             }
             return (lowercase.starts_with("screenshot") || lowercase.contains("screen shot"))
                 && !lowercase.ends_with("-ss")
-                && path.extension().map_or(false, |ext| ext == "png");
+                && path.extension().is_some_and(|ext| ext == "png");
         }
         false
     }
 
-    fn is_recent(&self, path: &PathBuf, max_age: Duration) -> bool {
-        if let Ok(metadata) = fs::metadata(path) {
-            if let Ok(created) = metadata.created().or_else(|_| metadata.modified()) {
-                return SystemTime::now()
-                    .duration_since(created)
-                    .unwrap_or(Duration::MAX)
-                    < max_age;
-            }
-        }
-        false
-    }
-
-    fn delete_file(&self, path: &PathBuf) -> Result<(), anyhow::Error> {
-        if let Err(e) = fs::remove_file(path) {
-            return Err(anyhow::anyhow!(
-                "Failed to delete file: {:?}, Error: {}",
-                path,
-                e
-            ));
-        }
-        Ok(())
-    }
-
-    async fn process_ss(&self, address: String, path: &PathBuf) -> Result<(), anyhow::Error> {
+    async fn process_ss(
+        &self,
+        context: &NamingContext,
+        path: &Path,
+    ) -> Result<PathBuf, anyhow::Error> {
         // create new filename
-        let mut new_filename = self.ai.get_name(address, path.clone()).await?;
-        new_filename += ".png";
+        let (suggestion, values) = self.get_name(context, path).await?;
 
-        // create new path
-        let parent = path.parent().unwrap_or(Path::new("."));
-        let new_path = parent.join(new_filename);
-
-        // copy file to new path
-        if let Err(e) = fs::copy(path.clone(), &new_path) {
-            return Err(anyhow::anyhow!(
-                "Failed to copy file: {:?} -> {:?}, Error: {}",
-                path.clone(),
-                new_path,
-                e
-            ));
-        }
-
-        // delete old file
-        self.delete_file(&path)
+        // move file to new path
+        self.rename(context, path, &suggestion, &values, "png")
+            .await
     }
 
     pub async fn process_new_ss(
         &self,
-        address: String,
-        path: &PathBuf,
-    ) -> Result<(), anyhow::Error> {
+        context: &NamingContext,
+        path: &Path,
+    ) -> Result<PathBuf, anyhow::Error> {
         if !self.is_screenshot_file(path) {
-            return Err(anyhow::anyhow!(
-                "file is not screenshot or not recent: {:?}",
-                path
-            ));
+            return Err(GogglesError::NotAScreenshot(path.to_path_buf()).into());
         }
 
         let path = self.modify_ss_path(path);
 
-        if !self.is_recent(&path, Duration::from_secs(60)) {
-            return Err(anyhow::anyhow!("Skipping old file: {:?}", path));
+        if self.journal.is_processed(&path) {
+            return Err(GogglesError::AlreadyRenamed(path).into());
         }
 
-        self.process_ss(address, &path).await
+        self.process_ss(context, &path).await
     }
 
+    /// Renames a file that just appeared in a watched folder.
+    pub async fn process_new_file(
+        &self,
+        context: &NamingContext,
+        path: &PathBuf,
+    ) -> Result<PathBuf, anyhow::Error> {
+        if self.journal.is_processed(path) {
+            return Err(GogglesError::AlreadyRenamed(path.to_path_buf()).into());
+        }
+
+        self.process_random_image(context, path).await
+    }
+
+    /// Renames `path` and returns where it ended up (or would, in a dry run).
     pub async fn process_random_image(
         &self,
-        address: String,
+        context: &NamingContext,
         path: &PathBuf,
-    ) -> Result<(), anyhow::Error> {
-        let file_type = match path.extension() {
-            Some(ext) => ext.to_str().unwrap(),
-            None => return Err(anyhow::anyhow!("Failed to get file extension")),
-        };
+    ) -> Result<PathBuf, anyhow::Error> {
+        let file_type = file_extension(path)?;
 
-        let parent = path.parent().unwrap_or(Path::new("."));
+        info!("Processing image: {:?}", path);
+        let (suggestion, values) = self.get_name(context, path).await?;
+
+        let new_path = self
+            .rename(context, path, &suggestion, &values, file_type)
+            .await?;
+
+        info!("New filename: {:?}", new_path);
+        Ok(new_path)
+    }
+}
 
-        println!("Processing image: {:?}", path);
-        let mut new_filename: String = self.ai.get_name(address, path.clone()).await?;
-        new_filename += &format!(".{}", file_type);
+fn is_already_exists(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<GogglesError>(),
+        Some(GogglesError::AlreadyExists(_))
+    )
+}
+
+fn file_extension(path: &Path) -> Result<&str, anyhow::Error> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .ok_or_else(|| GogglesError::InvalidName(format!("No file extension: {:?}", path)).into())
+}
+
+#[cfg(feature = "gui")]
+fn display_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
 
-        let new_path = parent.join(new_filename);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::fs;
 
-        println!("New filename: {:?}", new_path);
+    /// Suggests the same name for every file.
+    struct Fixed(&'static str);
 
-        if let Err(e) = fs::copy(path.clone(), &new_path) {
-            return Err(anyhow::anyhow!(
-                "Failed to copy file: {:?} -> {:?}, Error: {}",
-                path,
-                new_path,
-                e
-            ));
+    #[async_trait]
+    impl NamingBackend for Fixed {
+        fn id(&self) -> &str {
+            "fixed"
         }
 
-        self.delete_file(&path)
-            .map_err(|e| anyhow::anyhow!("Failed to delete file: {:?}, Error: {}", path, e))
+        async fn suggest_name(
+            &self,
+            _path: &Path,
+            _context: &NamingContext,
+        ) -> Result<NameSuggestion, anyhow::Error> {
+            Ok(NameSuggestion {
+                name: self.0.to_string(),
+                backend: self.id().to_string(),
+            })
+        }
+    }
+
+    struct Folder {
+        dir: tempfile::TempDir,
+        journal: RenameJournal,
+    }
+
+    impl Folder {
+        /// A folder already holding `taken`.
+        fn with(taken: &[&str]) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            fs::create_dir(dir.path().join("shots")).unwrap();
+            let journal = RenameJournal::new(dir.path().join("journal.jsonl"));
+            let folder = Self { dir, journal };
+            for name in taken {
+                fs::write(folder.path(name), b"taken").unwrap();
+            }
+            folder
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.path().join("shots").join(name)
+        }
+
+        fn image(&self, name: &str) -> PathBuf {
+            let path = self.path(name);
+            image::RgbImage::new(4, 4).save(&path).unwrap();
+            path
+        }
+
+        fn manager(&self, policy: CollisionPolicy) -> SSManager {
+            SSManager::new(Arc::new(Fixed("sunset")), self.journal.clone())
+                .with_collision_policy(policy)
+        }
+    }
+
+    const SCREENSHOT: &str = "Screenshot 2026-01-01 at 10.00.00.png";
+
+    #[tokio::test]
+    async fn suffix_policy_numbers_taken_names() {
+        let folder = Folder::with(&["sunset.png", "sunset-2.png"]);
+        let path = folder.image(SCREENSHOT);
+
+        let new_path = folder
+            .manager(CollisionPolicy::Suffix)
+            .process_new_ss(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+
+        assert_eq!(new_path, folder.path("sunset-3.png"));
+        assert!(!path.exists());
+        assert_eq!(fs::read(folder.path("sunset.png")).unwrap(), b"taken");
+        assert!(folder.journal.is_renamed_file(&new_path));
+    }
+
+    #[tokio::test]
+    async fn timestamp_policy_appends_the_time() {
+        let folder = Folder::with(&["sunset.png"]);
+        let path = folder.image(SCREENSHOT);
+
+        let new_path = folder
+            .manager(CollisionPolicy::Timestamp)
+            .process_new_ss(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+
+        let name = new_path.file_name().unwrap().to_str().unwrap();
+        let stamp = name
+            .strip_prefix("sunset-")
+            .and_then(|rest| rest.strip_suffix(".png"))
+            .unwrap();
+        assert!(chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S").is_ok());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn skip_policy_leaves_the_file_alone() {
+        let folder = Folder::with(&["sunset.png"]);
+        let path = folder.image(SCREENSHOT);
+
+        let error = folder
+            .manager(CollisionPolicy::Skip)
+            .process_new_ss(&NamingContext::default(), &path)
+            .await
+            .unwrap_err();
+
+        assert!(is_already_exists(&error));
+        assert!(path.exists());
+        assert_eq!(fs::read(folder.path("sunset.png")).unwrap(), b"taken");
+        assert!(folder.journal.entries().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn other_files_honour_the_policy_too() {
+        let folder = Folder::with(&["sunset.jpg"]);
+        let path = folder.path("IMG_0001.jpg");
+        image::RgbImage::new(4, 4).save(&path).unwrap();
+
+        let new_path = folder
+            .manager(CollisionPolicy::Suffix)
+            .process_new_file(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+        assert_eq!(new_path, folder.path("sunset-2.jpg"));
+
+        let path = folder.image("IMG_0002.png");
+        fs::write(folder.path("sunset.png"), b"taken").unwrap();
+        let error = folder
+            .manager(CollisionPolicy::Skip)
+            .process_new_file(&NamingContext::default(), &path)
+            .await
+            .unwrap_err();
+        assert!(is_already_exists(&error));
+        assert!(path.exists());
+    }
+
+    #[tokio::test]
+    async fn counter_picks_the_first_free_number() {
+        let folder = Folder::with(&["shot-1.png", "shot-2.png"]);
+        let path = folder.image(SCREENSHOT);
+
+        let new_path = folder
+            .manager(CollisionPolicy::Skip)
+            .with_template(NameTemplate::parse("shot-{counter}").unwrap())
+            .process_new_ss(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+
+        assert_eq!(new_path, folder.path("shot-3.png"));
+        assert_eq!(folder.journal.entries().unwrap()[0].backend, "template");
+    }
+
+    #[tokio::test]
+    async fn dry_run_moves_nothing() {
+        let folder = Folder::with(&["sunset.png"]);
+        let path = folder.image(SCREENSHOT);
+
+        let new_path = folder
+            .manager(CollisionPolicy::Suffix)
+            .with_dry_run(true)
+            .process_new_ss(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+
+        assert_eq!(new_path, folder.path("sunset-2.png"));
+        assert!(path.exists());
+        assert!(!new_path.exists());
+        assert!(folder.journal.entries().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn files_that_already_have_the_name_stay_put() {
+        let folder = Folder::with(&[]);
+        let path = folder.image("sunset.png");
+        let manager = folder.manager(CollisionPolicy::Suffix);
+
+        let new_path = manager
+            .process_new_file(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+        assert_eq!(new_path, path);
+        assert!(path.exists());
+        assert!(!folder.path("sunset-2.png").exists());
+    }
+
+    #[cfg(feature = "gui")]
+    #[tokio::test]
+    async fn plans_leave_files_that_already_have_the_name() {
+        let folder = Folder::with(&[]);
+        let path = folder.image("sunset.png");
+        let manager = folder.manager(CollisionPolicy::Suffix);
+
+        let plan = manager
+            .plan_rename(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+        assert_eq!(plan.new_path, None);
+        assert_eq!(plan.collision, None);
+        assert_eq!(plan.reason, "Already named sunset.png");
+
+        let counted = manager.with_template(NameTemplate::parse("sunset{counter}").unwrap());
+        let path = folder.image("sunset1.png");
+        let plan = counted
+            .plan_rename(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+        assert_eq!(plan.new_path, None);
+    }
+
+    #[cfg(feature = "gui")]
+    #[tokio::test]
+    async fn plans_report_collisions_and_apply_them() {
+        let folder = Folder::with(&["sunset.png"]);
+        let path = folder.image(SCREENSHOT);
+        let manager = folder.manager(CollisionPolicy::Suffix);
+
+        let plan = manager
+            .plan_rename(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+        assert_eq!(plan.new_path, Some(folder.path("sunset-2.png")));
+        assert_eq!(plan.collision, Some(folder.path("sunset.png")));
+        assert_eq!(plan.reason, "sunset.png already exists, using sunset-2.png");
+        assert_eq!(plan.backend, "fixed");
+        // planning moves nothing
+        assert!(path.exists());
+
+        let new_path = manager
+            .apply_plan(&NamingContext::default(), &plan, None)
+            .await
+            .unwrap();
+        assert_eq!(new_path, folder.path("sunset-2.png"));
+        assert!(!path.exists());
+        assert_eq!(folder.journal.entries().unwrap()[0].backend, "fixed");
+    }
+
+    #[cfg(feature = "gui")]
+    #[tokio::test]
+    async fn applies_an_edited_name() {
+        let folder = Folder::with(&["beach.png"]);
+        let path = folder.image(SCREENSHOT);
+        let manager = folder.manager(CollisionPolicy::Suffix);
+        let plan = manager
+            .plan_rename(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+
+        let new_path = manager
+            .apply_plan(&NamingContext::default(), &plan, Some("beach / trip"))
+            .await
+            .unwrap();
+        assert_eq!(new_path, folder.path("beach-trip.png"));
+        assert_eq!(folder.journal.entries().unwrap()[0].backend, "manual");
+
+        // keeping the current name is a no-op, not a numbered copy
+        let unchanged = manager
+            .apply_plan(
+                &NamingContext::default(),
+                &RenamePlan {
+                    old_path: new_path.clone(),
+                    ..plan
+                },
+                Some("beach-trip"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(unchanged, new_path);
+        assert!(!folder.path("beach-trip-2.png").exists());
+
+        // an edited name that is taken follows the collision policy
+        let plan = manager
+            .plan_rename(&NamingContext::default(), &new_path)
+            .await
+            .unwrap();
+        let renamed = manager
+            .apply_plan(&NamingContext::default(), &plan, Some("beach"))
+            .await
+            .unwrap();
+        assert_eq!(renamed, folder.path("beach-2.png"));
+    }
+
+    #[cfg(feature = "gui")]
+    #[tokio::test]
+    async fn applying_a_plan_picks_another_name_when_the_target_appeared() {
+        let folder = Folder::with(&[]);
+        let path = folder.image(SCREENSHOT);
+        let manager = folder.manager(CollisionPolicy::Suffix);
+        let plan = manager
+            .plan_rename(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+        assert_eq!(plan.new_path, Some(folder.path("sunset.png")));
+
+        // another file takes the name while the user looks at the plan
+        fs::write(folder.path("sunset.png"), b"taken").unwrap();
+
+        let new_path = manager
+            .apply_plan(&NamingContext::default(), &plan, None)
+            .await
+            .unwrap();
+        assert_eq!(new_path, folder.path("sunset-2.png"));
+        assert_eq!(fs::read(folder.path("sunset.png")).unwrap(), b"taken");
+        assert_eq!(folder.journal.entries().unwrap()[0].new_path, new_path);
+    }
+
+    #[cfg(feature = "gui")]
+    #[tokio::test]
+    async fn applying_a_plan_honours_dry_run() {
+        let folder = Folder::with(&[]);
+        let path = folder.image(SCREENSHOT);
+        let manager = folder.manager(CollisionPolicy::Suffix).with_dry_run(true);
+        let plan = manager
+            .plan_rename(&NamingContext::default(), &path)
+            .await
+            .unwrap();
+
+        let new_path = manager
+            .apply_plan(&NamingContext::default(), &plan, None)
+            .await
+            .unwrap();
+        assert_eq!(new_path, folder.path("sunset.png"));
+        assert!(path.exists());
+        assert!(!new_path.exists());
+        assert!(folder.journal.entries().unwrap().is_empty());
     }
 }
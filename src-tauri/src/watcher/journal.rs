@@ -0,0 +1,380 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::watcher::error::GogglesError;
+use crate::watcher::fsops::{move_file, write_atomic};
+use crate::watcher::paths::AppPaths;
+
+// the daemon and the UI commands share one process, serialize file access
+static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub original_path: PathBuf,
+    pub new_path: PathBuf,
+    pub timestamp: u64,
+    pub backend: String,
+    pub address: String,
+    #[serde(default)]
+    pub undone: bool,
+}
+
+/// Size and modification time of the journal file, a change means
+/// someone else wrote to it.
+type FileStamp = (u64, Option<SystemTime>);
+
+/// What the lookups need from the journal, so file events don't parse the
+/// whole file.
+#[derive(Debug, Default)]
+struct JournalIndex {
+    /// `new_path` of every rename that wasn't undone.
+    renamed: HashSet<PathBuf>,
+    /// `original_path` of every undone rename.
+    undone: HashSet<PathBuf>,
+    last_id: u64,
+    /// The file as it was indexed, `None` when the index must be rebuilt.
+    stamp: Option<FileStamp>,
+}
+
+impl JournalIndex {
+    fn build(entries: &[JournalEntry], stamp: FileStamp) -> Self {
+        let mut index = Self {
+            stamp: Some(stamp),
+            ..Self::default()
+        };
+        for entry in entries {
+            index.add(entry);
+        }
+        index
+    }
+
+    fn add(&mut self, entry: &JournalEntry) {
+        self.last_id = self.last_id.max(entry.id);
+        if entry.undone {
+            self.undone.insert(entry.original_path.clone());
+        } else {
+            self.renamed.insert(entry.new_path.clone());
+        }
+    }
+}
+
+fn file_stamp(path: &Path) -> FileStamp {
+    match fs::metadata(path) {
+        Ok(metadata) => (metadata.len(), metadata.modified().ok()),
+        Err(_) => (0, None),
+    }
+}
+
+/// Append-only JSON Lines record of every rename, used to undo bad names.
+#[derive(Debug, Clone)]
+pub struct RenameJournal {
+    path: PathBuf,
+    index: Arc<Mutex<JournalIndex>>,
+}
+
+impl RenameJournal {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            index: Arc::default(),
+        }
+    }
+
+    /// The journal in the app state directory.
+    pub fn open() -> Result<Self, anyhow::Error> {
+        Ok(Self::new(AppPaths::new()?.journal_file()))
+    }
+
+    /// Every readable entry. Lines that don't parse, like one cut short
+    /// by a crash or a full disk, are logged and skipped.
+    fn read_entries(&self) -> Result<Vec<JournalEntry>, anyhow::Error> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        let content = String::from_utf8_lossy(&fs::read(&self.path)?).into_owned();
+        let entries = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(number, line)| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    warn!(
+                        "Skipping unreadable line {} of {}: {}",
+                        number + 1,
+                        self.path.display(),
+                        e
+                    );
+                    None
+                }
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    /// Replaces the journal through a temp file, so a failed write leaves
+    /// the old one intact.
+    fn write_entries(&self, entries: &[JournalEntry]) -> Result<(), anyhow::Error> {
+        let mut content = String::new();
+        for entry in entries {
+            content += &serde_json::to_string(entry)?;
+            content.push('\n');
+        }
+
+        write_atomic(&self.path, content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Rebuilds `index` when the file changed since it was indexed, e.g.
+    /// by another goggles process. Call with `JOURNAL_LOCK` held.
+    fn refresh(&self, index: &mut JournalIndex) -> Result<(), anyhow::Error> {
+        let stamp = file_stamp(&self.path);
+        if index.stamp != Some(stamp) {
+            *index = JournalIndex::build(&self.read_entries()?, stamp);
+        }
+        Ok(())
+    }
+
+    /// Answers `check` from the index. Fails closed: when the journal
+    /// can't be read, every file counts as handled, so our own output is
+    /// never renamed again.
+    fn lookup(&self, check: impl FnOnce(&JournalIndex) -> bool) -> bool {
+        let _guard = JOURNAL_LOCK.lock().unwrap();
+        let mut index = self.index.lock().unwrap();
+        match self.refresh(&mut index) {
+            Ok(()) => check(&index),
+            Err(e) => {
+                error!("Failed to read rename journal: {:?}", e);
+                true
+            }
+        }
+    }
+
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, anyhow::Error> {
+        let _guard = JOURNAL_LOCK.lock().unwrap();
+        self.read_entries()
+    }
+
+    /// Whether `path` is the result of a rename we did, so watchers don't
+    /// pick up their own output.
+    pub fn is_renamed_file(&self, path: &Path) -> bool {
+        self.lookup(|index| index.renamed.contains(path))
+    }
+
+    /// Whether Goggles already dealt with `path`: it is one of our renames,
+    /// or a rename of it was undone and must not be redone.
+    pub fn is_processed(&self, path: &Path) -> bool {
+        self.lookup(|index| index.renamed.contains(path) || index.undone.contains(path))
+    }
+
+    pub fn record(
+        &self,
+        original_path: &Path,
+        new_path: &Path,
+        backend: &str,
+        address: &str,
+    ) -> Result<JournalEntry, anyhow::Error> {
+        let _guard = JOURNAL_LOCK.lock().unwrap();
+        let mut index = self.index.lock().unwrap();
+        self.refresh(&mut index)?;
+
+        let id = index.last_id + 1;
+        let entry = JournalEntry {
+            id,
+            original_path: original_path.to_path_buf(),
+            new_path: new_path.to_path_buf(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            backend: backend.to_string(),
+            address: address.to_string(),
+            undone: false,
+        };
+
+        let mut line = format!("{}\n", serde_json::to_string(&entry)?);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        // start on a fresh line after one cut short, or both are lost
+        if ends_without_newline(&mut file)? {
+            line.insert(0, '\n');
+        }
+        file.write_all(line.as_bytes())?;
+
+        index.add(&entry);
+        // anything but our own line means another process wrote meanwhile
+        let before = index.stamp.map_or(0, |(len, _)| len);
+        let after = file_stamp(&self.path);
+        index.stamp = (after.0 == before + line.len() as u64).then_some(after);
+        Ok(entry)
+    }
+
+    /// Moves the file of entry `id` back to its original name.
+    pub fn undo_rename(&self, id: u64) -> Result<JournalEntry, anyhow::Error> {
+        let _guard = JOURNAL_LOCK.lock().unwrap();
+
+        let mut entries = self.read_entries()?;
+        let entry = match entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => entry,
+            None => return Err(anyhow::anyhow!("No rename with id {} in journal", id)),
+        };
+
+        if entry.undone {
+            return Err(anyhow::anyhow!("Rename {} was already undone", id));
+        }
+        if !entry.new_path.exists() {
+            return Err(
+                anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::NotFound)).context(
+                    format!("Renamed file no longer exists: {:?}", entry.new_path),
+                ),
+            );
+        }
+        if entry.original_path.exists() {
+            return Err(GogglesError::AlreadyExists(entry.original_path.clone()).into());
+        }
+
+        move_file(&entry.new_path, &entry.original_path)?;
+
+        entry.undone = true;
+        let undone = entry.clone();
+        self.write_entries(&entries)?;
+        *self.index.lock().unwrap() = JournalIndex::build(&entries, file_stamp(&self.path));
+        Ok(undone)
+    }
+
+    /// Undoes the most recent rename that has not been undone yet.
+    pub fn undo_last(&self) -> Result<JournalEntry, anyhow::Error> {
+        let last = self
+            .entries()?
+            .into_iter()
+            .rev()
+            .find(|entry| !entry.undone)
+            .ok_or_else(|| anyhow::anyhow!("Nothing to undo"))?;
+        self.undo_rename(last.id)
+    }
+}
+
+fn ends_without_newline(file: &mut fs::File) -> Result<bool, std::io::Error> {
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+    let mut last = [0; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal() -> (tempfile::TempDir, RenameJournal) {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = RenameJournal::new(dir.path().join("journal.jsonl"));
+        (dir, journal)
+    }
+
+    #[test]
+    fn tracks_renames_and_undos() {
+        let (dir, journal) = journal();
+        let original = dir.path().join("shot.png");
+        let renamed = dir.path().join("cat.png");
+        fs::write(&renamed, b"").unwrap();
+
+        assert!(!journal.is_processed(&renamed));
+        let entry = journal.record(&original, &renamed, "local", "").unwrap();
+        assert!(journal.is_renamed_file(&renamed));
+        assert!(journal.is_processed(&renamed));
+        assert!(!journal.is_processed(&original));
+
+        journal.undo_rename(entry.id).unwrap();
+        assert!(!journal.is_renamed_file(&renamed));
+        assert!(journal.is_processed(&original));
+    }
+
+    #[test]
+    fn numbers_entries_in_order() {
+        let (dir, journal) = journal();
+        let first = journal
+            .record(&dir.path().join("a"), &dir.path().join("b"), "local", "")
+            .unwrap();
+        let second = journal
+            .record(&dir.path().join("c"), &dir.path().join("d"), "local", "")
+            .unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+    }
+
+    #[test]
+    fn sees_writes_from_other_processes() {
+        let (dir, journal) = journal();
+        let other = RenameJournal::new(journal.path.clone());
+        let renamed = dir.path().join("cat.png");
+
+        assert!(!journal.is_processed(&renamed));
+        other
+            .record(&dir.path().join("shot.png"), &renamed, "local", "")
+            .unwrap();
+        assert!(journal.is_processed(&renamed));
+
+        let next = journal
+            .record(&dir.path().join("a"), &dir.path().join("b"), "local", "")
+            .unwrap();
+        assert_eq!(next.id, 2);
+    }
+
+    #[test]
+    fn survives_a_line_cut_short() {
+        let (dir, journal) = journal();
+        let renamed = dir.path().join("cat.png");
+        journal
+            .record(&dir.path().join("shot.png"), &renamed, "local", "")
+            .unwrap();
+        let mut file = OpenOptions::new().append(true).open(&journal.path).unwrap();
+        file.write_all(br#"{"id":2,"original_path":"/tmp/a"#)
+            .unwrap();
+
+        let reopened = RenameJournal::new(journal.path.clone());
+        assert!(reopened.is_processed(&renamed));
+        assert_eq!(reopened.entries().unwrap().len(), 1);
+
+        let next = reopened
+            .record(
+                &dir.path().join("b.png"),
+                &dir.path().join("dog.png"),
+                "local",
+                "",
+            )
+            .unwrap();
+        assert_eq!(next.id, 2);
+        assert_eq!(reopened.entries().unwrap().len(), 2);
+
+        // undo rewrites the file without the broken line
+        fs::write(dir.path().join("dog.png"), b"").unwrap();
+        reopened.undo_rename(next.id).unwrap();
+        assert_eq!(
+            fs::read_to_string(&journal.path).unwrap().lines().count(),
+            2
+        );
+        assert!(!dir.path().join("journal.jsonl.tmp").exists());
+    }
+
+    #[test]
+    fn unreadable_journal_fails_closed() {
+        let (dir, _) = journal();
+        // a directory where the file should be can't be read
+        let journal = RenameJournal::new(dir.path().to_path_buf());
+        assert!(journal.is_processed(&dir.path().join("shot.png")));
+    }
+}
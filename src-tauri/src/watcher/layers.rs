@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::watcher::config::GogglesConfig;
+use crate::watcher::paths::home_override;
+
+const ENV_PREFIX: &str = "GOGGLES_";
+
+/// Where an effective config value came from, lowest precedence first.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigLayer {
+    Default,
+    /// `/etc/goggles/config.toml`
+    System,
+    /// `~/.goggles/config.json`, written by the app
+    UserJson,
+    /// `~/.config/goggles/config.toml`, for dotfile managed machines
+    UserToml,
+    /// `GOGGLES_*` environment variables
+    Env,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    pub config: GogglesConfig,
+    /// Layer that set each top-level key of `config`.
+    pub sources: BTreeMap<String, ConfigLayer>,
+}
+
+pub fn get_system_config_path() -> PathBuf {
+    PathBuf::from("/etc/goggles/config.toml")
+}
+
+pub fn get_user_toml_path() -> Option<PathBuf> {
+    if let Some(root) = home_override() {
+        return Some(root.join("config.toml"));
+    }
+
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_home.join("goggles").join("config.toml"))
+}
+
+fn read_toml(path: &Path) -> Result<Option<Map<String, Value>>, anyhow::Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let table: Map<String, Value> = toml::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Invalid TOML in {}: {}", path.display(), e))?;
+    Ok(Some(table))
+}
+
+/// Collects `GOGGLES_<KEY>` variables for keys the config knows about.
+fn read_env(
+    defaults: &Map<String, Value>,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Map<String, Value> {
+    let mut layer = Map::new();
+    for (name, value) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let key = key.to_lowercase();
+        if !defaults.contains_key(&key) {
+            continue;
+        }
+        let value = env_value(defaults, &key, value);
+        layer.insert(key, value);
+    }
+    layer
+}
+
+/// Reads `raw` as the type of the `key` field: verbatim when it takes a
+/// string, including optional ones whose default is `null`, otherwise as
+/// JSON, e.g. numbers, booleans and lists. Values that fit neither stay
+/// strings, so loading reports them.
+fn env_value(defaults: &Map<String, Value>, key: &str, raw: String) -> Value {
+    let fits = |value: &Value| {
+        let mut config = defaults.clone();
+        config.insert(key.to_string(), value.clone());
+        serde_json::from_value::<GogglesConfig>(Value::Object(config)).is_ok()
+    };
+
+    if fits(&Value::String(raw.clone())) {
+        return Value::String(raw);
+    }
+    match serde_json::from_str(&raw) {
+        Ok(parsed) if fits(&parsed) => parsed,
+        _ => Value::String(raw),
+    }
+}
+
+fn merge(
+    target: &mut Map<String, Value>,
+    sources: &mut BTreeMap<String, ConfigLayer>,
+    layer: Map<String, Value>,
+    origin: ConfigLayer,
+) {
+    for (key, value) in layer {
+        match (target.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(overlay)) => {
+                existing.extend(overlay);
+            }
+            (_, value) => {
+                target.insert(key.clone(), value);
+            }
+        }
+        sources.insert(key, origin);
+    }
+}
+
+/// Merges defaults, the system file, the user JSON and TOML files and the
+/// environment, later layers winning per key.
+pub fn load_layered() -> Result<LayeredConfig, anyhow::Error> {
+    load_layered_from(
+        &get_system_config_path(),
+        &GogglesConfig::get_config_path()?,
+        get_user_toml_path().as_deref(),
+        std::env::vars(),
+    )
+}
+
+fn load_layered_from(
+    system_path: &Path,
+    user_json_path: &Path,
+    user_toml_path: Option<&Path>,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<LayeredConfig, anyhow::Error> {
+    let Value::Object(defaults) = serde_json::to_value(GogglesConfig::default())? else {
+        return Err(anyhow::anyhow!("Default config is not an object"));
+    };
+
+    let mut merged = Map::new();
+    let mut sources = BTreeMap::new();
+    merge(
+        &mut merged,
+        &mut sources,
+        defaults.clone(),
+        ConfigLayer::Default,
+    );
+
+    if let Some(layer) = read_toml(system_path)? {
+        merge(&mut merged, &mut sources, layer, ConfigLayer::System);
+    }
+
+    let user_json =
+        GogglesConfig::load_user_json_from(user_json_path).map_err(|e| anyhow::anyhow!("{}", e))?;
+    if let Some(Value::Object(layer)) = user_json {
+        merge(&mut merged, &mut sources, layer, ConfigLayer::UserJson);
+    }
+
+    if let Some(path) = user_toml_path {
+        if let Some(layer) = read_toml(path)? {
+            merge(&mut merged, &mut sources, layer, ConfigLayer::UserToml);
+        }
+    }
+
+    merge(
+        &mut merged,
+        &mut sources,
+        read_env(&defaults, vars),
+        ConfigLayer::Env,
+    );
+
+    let config: GogglesConfig = serde_json::from_value(Value::Object(merged))?;
+    Ok(LayeredConfig { config, sources })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_layer_survives_saving_the_address() {
+        let dir = tempfile::tempdir().unwrap();
+        let system_path = dir.path().join("system.toml");
+        fs::write(&system_path, "name_template = \"{date}-{ai}\"\n").unwrap();
+        let json_path = dir.path().join("config.json");
+
+        let config = GogglesConfig {
+            address: "0xabc".to_string(),
+            ..GogglesConfig::default()
+        };
+        config.save_keys_to(&json_path, &["address"]).unwrap();
+
+        let layered = load_layered_from(&system_path, &json_path, None, []).unwrap();
+        assert_eq!(layered.config.address, "0xabc");
+        assert_eq!(layered.config.name_template, "{date}-{ai}");
+        assert_eq!(layered.sources["address"], ConfigLayer::UserJson);
+        assert_eq!(layered.sources["name_template"], ConfigLayer::System);
+        assert_eq!(layered.sources["server_url"], ConfigLayer::Default);
+    }
+
+    #[test]
+    fn layers_apply_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let system_path = dir.path().join("system.toml");
+        let json_path = dir.path().join("config.json");
+        let toml_path = dir.path().join("config.toml");
+        fs::write(
+            &system_path,
+            "name_template = \"{date}\"\nmax_concurrent_jobs = 2\nserver_max_retries = 1\n",
+        )
+        .unwrap();
+        fs::write(&json_path, r#"{"version":1,"name_template":"{app}"}"#).unwrap();
+        fs::write(&toml_path, "max_concurrent_jobs = 3\n").unwrap();
+
+        let env = [
+            ("GOGGLES_SERVER_MAX_RETRIES", "5"),
+            ("GOGGLES_SERVER_URL", "http://localhost:9"),
+            ("GOGGLES_NOT_A_KEY", "ignored"),
+            ("OTHER_ADDRESS", "ignored"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let layered = load_layered_from(&system_path, &json_path, Some(&toml_path), env).unwrap();
+
+        assert_eq!(layered.config.name_template, "{app}");
+        assert_eq!(layered.config.max_concurrent_jobs, 3);
+        assert_eq!(layered.config.server_max_retries, 5);
+        assert_eq!(layered.config.server_url, "http://localhost:9");
+        assert_eq!(layered.sources["name_template"], ConfigLayer::UserJson);
+        assert_eq!(
+            layered.sources["max_concurrent_jobs"],
+            ConfigLayer::UserToml
+        );
+        assert_eq!(layered.sources["server_max_retries"], ConfigLayer::Env);
+        assert_eq!(layered.sources["server_url"], ConfigLayer::Env);
+        assert!(!layered.sources.contains_key("not_a_key"));
+    }
+
+    #[test]
+    fn env_values_take_the_type_of_their_field() {
+        let dir = tempfile::tempdir().unwrap();
+        let env = [
+            ("GOGGLES_SERVER_AUTH_HEADER", "12345"),
+            ("GOGGLES_ADDRESS", "0x123"),
+            ("GOGGLES_SERVER_TIMEOUT_SECS", "30"),
+            ("GOGGLES_SERVER_CA_BUNDLE", "/etc/ssl/corp.pem"),
+            ("GOGGLES_PRIVACY_RULES", r#"["*bank*"]"#),
+            ("GOGGLES_NAME_TEMPLATE", "null"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let layered = load_layered_from(
+            &dir.path().join("system.toml"),
+            &dir.path().join("config.json"),
+            None,
+            env,
+        )
+        .unwrap();
+        let config = layered.config;
+        assert_eq!(config.server_auth_header.as_deref(), Some("12345"));
+        assert_eq!(config.address, "0x123");
+        assert_eq!(config.server_timeout_secs, Some(30));
+        assert_eq!(
+            config.server_ca_bundle,
+            Some(PathBuf::from("/etc/ssl/corp.pem"))
+        );
+        assert_eq!(config.privacy_rules, vec!["*bank*".to_string()]);
+        assert_eq!(config.name_template, "null");
+    }
+
+    #[test]
+    fn env_values_of_the_wrong_type_fail_loading() {
+        let dir = tempfile::tempdir().unwrap();
+        let env = [("GOGGLES_MAX_CONCURRENT_JOBS", "many")]
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        assert!(load_layered_from(
+            &dir.path().join("system.toml"),
+            &dir.path().join("config.json"),
+            None,
+            env,
+        )
+        .is_err());
+    }
+}
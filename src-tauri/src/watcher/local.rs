@@ -0,0 +1,200 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use image::DynamicImage;
+
+use crate::watcher::naming::{NameSuggestion, NamingBackend, NamingContext};
+
+const MAX_APP_WORDS: usize = 2;
+const MAX_TITLE_WORDS: usize = 4;
+
+const PALETTE: [(&str, [u8; 3]); 12] = [
+    ("black", [0, 0, 0]),
+    ("white", [255, 255, 255]),
+    ("gray", [128, 128, 128]),
+    ("red", [220, 40, 40]),
+    ("orange", [245, 140, 30]),
+    ("yellow", [240, 220, 50]),
+    ("green", [50, 170, 70]),
+    ("teal", [30, 150, 150]),
+    ("blue", [40, 90, 220]),
+    ("purple", [130, 60, 190]),
+    ("pink", [240, 120, 180]),
+    ("brown", [130, 85, 45]),
+];
+
+/// Names files from deterministic signals available on disk: the frontmost
+/// window at capture time, the dominant color, dimensions and timestamp.
+/// Never touches the network, so it works as an offline fallback.
+#[derive(Debug, Clone, Default)]
+pub struct LocalBackend {}
+
+impl LocalBackend {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn build_name(path: &Path, context: &NamingContext) -> Result<String, anyhow::Error> {
+        let mut parts: Vec<String> = Vec::new();
+
+        if let Some(frontmost) = &context.frontmost {
+            parts.push(slugify(&frontmost.app_name, MAX_APP_WORDS));
+            if let Some(title) = &frontmost.window_title {
+                parts.push(slugify(title, MAX_TITLE_WORDS));
+            }
+        }
+
+        // Non-image files still get a name from the timestamp alone
+        if let Ok(img) = image::open(path) {
+            if let Some(color) = dominant_color(&img) {
+                parts.push(color.to_string());
+            }
+            parts.push(format!("{}x{}", img.width(), img.height()));
+        }
+
+        let metadata = fs::metadata(path)?;
+        let created = metadata.created().or_else(|_| metadata.modified())?;
+        let created: DateTime<Local> = created.into();
+        parts.push(created.format("%Y%m%d-%H%M%S").to_string());
+
+        parts.retain(|part| !part.is_empty());
+        Ok(parts.join("-"))
+    }
+}
+
+#[async_trait]
+impl NamingBackend for LocalBackend {
+    fn id(&self) -> &str {
+        "local"
+    }
+
+    async fn suggest_name(
+        &self,
+        path: &Path,
+        context: &NamingContext,
+    ) -> Result<NameSuggestion, anyhow::Error> {
+        // decoding a Retina screenshot takes a while, keep it off the runtime
+        let path: PathBuf = path.to_path_buf();
+        let context = context.clone();
+        let name = tokio::task::spawn_blocking(move || Self::build_name(&path, &context)).await??;
+
+        Ok(NameSuggestion {
+            name,
+            backend: self.id().to_string(),
+        })
+    }
+}
+
+/// Lowercases `raw` and keeps at most `max_words` ASCII alphanumeric words,
+/// joined by hyphens.
+fn slugify(raw: &str, max_words: usize) -> String {
+    raw.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .take(max_words)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Maps every pixel of a thumbnail to the nearest palette entry and returns
+/// the most common one.
+fn dominant_color(img: &DynamicImage) -> Option<&'static str> {
+    let thumbnail = img.thumbnail(32, 32).to_rgb8();
+    let mut counts = [0usize; PALETTE.len()];
+
+    for pixel in thumbnail.pixels() {
+        let nearest = PALETTE
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (_, rgb))| {
+                rgb.iter()
+                    .zip(pixel.0.iter())
+                    .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .map(|(index, _)| index)?;
+        counts[nearest] += 1;
+    }
+
+    let mut best: Option<usize> = None;
+    for (index, count) in counts.iter().enumerate() {
+        if *count > 0 && best.is_none_or(|b| *count > counts[b]) {
+            best = Some(index);
+        }
+    }
+    best.map(|index| PALETTE[index].0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher::macos::FrontmostWindow;
+    use image::{Rgb, RgbImage};
+
+    fn is_timestamp(part: &str) -> bool {
+        let digits = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+        matches!(part.split_once('-'), Some((date, time)) if digits(date, 8) && digits(time, 6))
+    }
+
+    #[test]
+    fn slugify_keeps_ascii_words() {
+        assert_eq!(slugify("Google Chrome", MAX_APP_WORDS), "google-chrome");
+        assert_eq!(
+            slugify("  Re: [PR #42] Fix -- the   build!  ", MAX_TITLE_WORDS),
+            "re-pr-42-fix"
+        );
+        // letters outside ASCII split words and are dropped
+        assert_eq!(slugify("Café Über", MAX_TITLE_WORDS), "caf-ber");
+        assert_eq!(slugify("日本語 Notes", MAX_TITLE_WORDS), "notes");
+        assert_eq!(slugify("日本語", MAX_TITLE_WORDS), "");
+        assert_eq!(slugify("one two three", 0), "");
+    }
+
+    #[test]
+    fn dominant_color_of_a_solid_image() {
+        let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 48, Rgb([230, 30, 30])));
+        assert_eq!(dominant_color(&red), Some("red"));
+
+        let mut mostly_blue = RgbImage::from_pixel(64, 64, Rgb([40, 90, 220]));
+        for x in 0..16 {
+            mostly_blue.put_pixel(x, 0, Rgb([255, 255, 255]));
+        }
+        assert_eq!(
+            dominant_color(&DynamicImage::ImageRgb8(mostly_blue)),
+            Some("blue")
+        );
+    }
+
+    #[test]
+    fn names_other_files_by_timestamp_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pdf");
+        fs::write(&path, b"%PDF-1.7").unwrap();
+
+        let name = LocalBackend::build_name(&path, &NamingContext::default()).unwrap();
+        assert!(is_timestamp(&name), "{}", name);
+    }
+
+    #[test]
+    fn names_screenshots_after_the_frontmost_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shot.png");
+        RgbImage::from_pixel(40, 20, Rgb([255, 255, 255]))
+            .save(&path)
+            .unwrap();
+        let context = NamingContext {
+            frontmost: Some(FrontmostWindow {
+                app_name: "Visual Studio Code".to_string(),
+                window_title: Some("main.rs — goggles: src-tauri/src".to_string()),
+            }),
+            ..NamingContext::default()
+        };
+
+        let name = LocalBackend::build_name(&path, &context).unwrap();
+        let prefix = "visual-studio-main-rs-goggles-src-white-40x20-";
+        assert!(name.starts_with(prefix), "{}", name);
+        assert!(is_timestamp(&name[prefix.len()..]), "{}", name);
+    }
+}
@@ -1,5 +1,6 @@
 use std::process::Command;
 
+#[cfg(feature = "gui")]
 pub fn get_finder_selection() -> Option<Vec<String>> {
     let script = r#"
         tell application "Finder"
@@ -51,6 +52,46 @@ pub fn get_finder_selection() -> Option<Vec<String>> {
     }
 }
 
-pub fn get_finder_selection_single() -> Option<String> {
-    get_finder_selection()?.into_iter().next()
+#[derive(Debug, Clone)]
+pub struct FrontmostWindow {
+    pub app_name: String,
+    pub window_title: Option<String>,
+}
+
+pub fn get_frontmost_window() -> Option<FrontmostWindow> {
+    let script = r#"
+        tell application "System Events"
+            set frontApp to first application process whose frontmost is true
+            set appName to name of frontApp
+            set windowTitle to ""
+            try
+                set windowTitle to name of front window of frontApp
+            end try
+            return appName & linefeed & windowTitle
+        end tell
+    "#;
+
+    let output = Command::new("osascript")
+        .arg("-e")
+        .arg(script)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines().map(|line| line.trim());
+
+    let app_name = lines.next().filter(|name| !name.is_empty())?.to_string();
+    let window_title = lines
+        .next()
+        .filter(|title| !title.is_empty())
+        .map(|title| title.to_string());
+
+    Some(FrontmostWindow {
+        app_name,
+        window_title,
+    })
 }
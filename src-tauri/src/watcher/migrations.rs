@@ -0,0 +1,107 @@
+use serde_json::{json, Map, Value};
+
+/// Version written by this build. Bump it together with a new entry in
+/// `MIGRATIONS` whenever the config shape changes.
+pub const CURRENT_CONFIG_VERSION: u32 = 1;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), anyhow::Error>;
+
+/// `MIGRATIONS[n]` upgrades a version `n` config to version `n + 1`.
+const MIGRATIONS: [Migration; CURRENT_CONFIG_VERSION as usize] = [v0_to_v1];
+
+/// Version of a raw config, files from before versioning count as 0.
+pub fn config_version(raw: &Value) -> Result<u32, anyhow::Error> {
+    match raw.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid config version: {}", version)),
+    }
+}
+
+/// Upgrades a raw config to `CURRENT_CONFIG_VERSION`, one step at a time.
+pub fn migrate(mut raw: Value) -> Result<Value, anyhow::Error> {
+    let version = config_version(&raw)?;
+    if version > CURRENT_CONFIG_VERSION {
+        return Err(anyhow::anyhow!(
+            "Config version {} is newer than supported version {}",
+            version,
+            CURRENT_CONFIG_VERSION
+        ));
+    }
+
+    let object = raw
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("Config must be a JSON object"))?;
+
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(object)
+            .map_err(|e| anyhow::anyhow!("Migration from version {} failed: {}", step, e))?;
+        object.insert("version".to_string(), json!(step + 1));
+    }
+
+    Ok(raw)
+}
+
+/// v0 was `{updated_at, address}` plus optional fields added over time
+/// without a version. v1 only adds the version, keys the user never set
+/// stay unset so the other config layers can fill them in.
+fn v0_to_v1(_config: &mut Map<String, Value>) -> Result<(), anyhow::Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher::config::{GogglesConfig, DEFAULT_SERVER_URL};
+
+    fn fixture(name: &str) -> Value {
+        let path = format!(
+            "{}/tests/fixtures/config/{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn migrated(name: &str) -> GogglesConfig {
+        let raw = migrate(fixture(name)).unwrap();
+        assert_eq!(raw["version"], json!(CURRENT_CONFIG_VERSION));
+        serde_json::from_value(raw).unwrap()
+    }
+
+    #[test]
+    fn migrates_the_original_shape() {
+        let config = migrated("v0_original");
+        assert_eq!(config.address, "0x1234");
+        assert_eq!(config.updated_at, 1717171717);
+        assert_eq!(config.server_url, DEFAULT_SERVER_URL);
+    }
+
+    #[test]
+    fn keeps_unversioned_server_settings() {
+        let config = migrated("v0_server_settings");
+        assert_eq!(config.server_url, "https://names.example.com");
+        assert_eq!(config.server_timeout_secs, Some(30));
+        assert_eq!(config.server_auth_header.as_deref(), Some("Bearer secret"));
+    }
+
+    #[test]
+    fn migrates_files_without_an_address() {
+        let config = migrated("v0_no_address");
+        assert_eq!(config.address, "");
+        assert_eq!(config.server_url, "https://names.example.com");
+    }
+
+    #[test]
+    fn leaves_unset_keys_unset() {
+        let raw = migrate(fixture("v0_original")).unwrap();
+        assert!(raw.get("name_template").is_none());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        assert!(migrate(fixture("future_version")).is_err());
+    }
+}
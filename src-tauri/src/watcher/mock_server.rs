@@ -0,0 +1,68 @@
+//! A minimal HTTP server standing in for the naming server in tests.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+pub const OK_BODY: &str = r#"{"success":true,"originalFilename":"shot.png","generatedFilename":"mock-name","imageSize":1,"mimeType":"image/jpeg"}"#;
+
+/// Answers requests with `responses` in order, repeating the last one.
+/// A status of 0 closes the connection without replying. Returns the server URL and the number of requests answered so far.
+pub fn mock_server(responses: Vec<(u16, &'static str)>) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+
+    let counter = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { break };
+            read_request(&mut stream);
+            let index = counter.fetch_add(1, Ordering::SeqCst);
+            let (status, body) = responses[index.min(responses.len() - 1)];
+            if status == 0 {
+                continue;
+            }
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    (url, requests)
+}
+
+fn read_request(stream: &mut TcpStream) {
+    let mut request = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        let Ok(read) = stream.read(&mut chunk) else {
+            return;
+        };
+        if read == 0 {
+            return;
+        }
+        request.extend_from_slice(&chunk[..read]);
+
+        let Some(header_end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+            continue;
+        };
+        let headers = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+        let body = &request[header_end + 4..];
+        let length = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|value| value.trim().parse::<usize>().ok());
+        let done = match length {
+            Some(length) => body.len() >= length,
+            None => !headers.contains("chunked") || body.ends_with(b"0\r\n\r\n"),
+        };
+        if done {
+            return;
+        }
+    }
+}
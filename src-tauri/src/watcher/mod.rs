@@ -1,7 +1,29 @@
 pub mod ai;
+pub mod collision;
 pub mod config;
+pub mod config_manager;
 pub mod daemon;
+pub mod error;
+pub mod fsops;
+pub mod http;
 pub mod image;
+pub mod journal;
+pub mod layers;
+pub mod local;
 pub mod macos;
+pub mod migrations;
+#[cfg(test)]
+mod mock_server;
+pub mod naming;
+pub mod paths;
+pub mod pending;
 pub mod pid;
+#[cfg(feature = "gui")]
+pub mod plan;
+pub mod privacy;
+pub mod queue;
+pub mod retry;
+pub mod sanitize;
+pub mod template;
+pub mod upload;
 pub mod utils;
@@ -0,0 +1,222 @@
+use std::path::Path;
+use std::sync::Arc;
+#[cfg(feature = "gui")]
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use log::warn;
+
+use crate::watcher::ai::OpenAI;
+use crate::watcher::config::GogglesConfig;
+use crate::watcher::error::GogglesError;
+use crate::watcher::local::LocalBackend;
+use crate::watcher::macos::FrontmostWindow;
+
+/// Builds the naming backend for a config, called again whenever it changes.
+pub type BackendFactory = fn(&GogglesConfig) -> Result<Arc<dyn NamingBackend>, GogglesError>;
+
+/// Extra information handed to a naming backend alongside the image.
+#[derive(Debug, Clone, Default)]
+pub struct NamingContext {
+    pub address: String,
+    /// Frontmost window when the screenshot was taken, if known.
+    pub frontmost: Option<FrontmostWindow>,
+    /// The file is a screenshot, so the screen `redact_regions` apply.
+    pub screenshot: bool,
+}
+
+impl NamingContext {
+    pub fn new(address: String) -> Self {
+        Self {
+            address,
+            frontmost: None,
+            screenshot: false,
+        }
+    }
+}
+
+/// A filename suggested by a backend, without extension.
+#[derive(Debug, Clone)]
+pub struct NameSuggestion {
+    pub name: String,
+    pub backend: String,
+}
+
+#[async_trait]
+pub trait NamingBackend: Send + Sync {
+    /// Short identifier of the backend, used in logs.
+    fn id(&self) -> &str;
+
+    async fn suggest_name(
+        &self,
+        path: &Path,
+        context: &NamingContext,
+    ) -> Result<NameSuggestion, anyhow::Error>;
+}
+
+/// Uses `primary` and falls back to `fallback` when it errors, except
+/// for errors the user has to act on, like running out of credits, and
+/// a paused server, which callers wait out instead.
+pub struct FallbackBackend {
+    primary: Arc<dyn NamingBackend>,
+    fallback: Arc<dyn NamingBackend>,
+}
+
+impl FallbackBackend {
+    pub fn new(primary: Arc<dyn NamingBackend>, fallback: Arc<dyn NamingBackend>) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait]
+impl NamingBackend for FallbackBackend {
+    fn id(&self) -> &str {
+        self.primary.id()
+    }
+
+    async fn suggest_name(
+        &self,
+        path: &Path,
+        context: &NamingContext,
+    ) -> Result<NameSuggestion, anyhow::Error> {
+        match self.primary.suggest_name(path, context).await {
+            Ok(suggestion) => Ok(suggestion),
+            Err(e) if !should_fall_back(&e) => Err(e),
+            Err(e) => {
+                warn!(
+                    "{} backend failed ({}), falling back to {}",
+                    self.primary.id(),
+                    e,
+                    self.fallback.id()
+                );
+                self.fallback.suggest_name(path, context).await
+            }
+        }
+    }
+}
+
+fn should_fall_back(error: &anyhow::Error) -> bool {
+    !matches!(
+        error.downcast_ref::<GogglesError>(),
+        Some(
+            GogglesError::InsufficientCredits { .. }
+                | GogglesError::Config(_)
+                | GogglesError::ServerUnavailable { .. }
+        )
+    )
+}
+
+/// The remote server, falling back to local heuristics when it fails.
+pub fn backend_from_config(config: &GogglesConfig) -> Result<Arc<dyn NamingBackend>, GogglesError> {
+    Ok(Arc::new(FallbackBackend::new(
+        Arc::new(OpenAI::from_config(config)?),
+        Arc::new(LocalBackend::new()),
+    )))
+}
+
+/// Keeps the backend, and with it the HTTP client and its connections,
+/// alive between calls. Rebuilt only when the settings it is built from
+/// change.
+#[cfg(feature = "gui")]
+#[derive(Default)]
+pub struct BackendCache {
+    cached: Mutex<Option<(GogglesConfig, Arc<dyn NamingBackend>)>>,
+}
+
+#[cfg(feature = "gui")]
+impl BackendCache {
+    pub fn get(&self, config: &GogglesConfig) -> Result<Arc<dyn NamingBackend>, GogglesError> {
+        let mut cached = self.cached.lock().unwrap();
+        if let Some((cached_config, backend)) = cached.as_ref() {
+            if cached_config.same_backend_settings(config) {
+                return Ok(backend.clone());
+            }
+        }
+        let backend = backend_from_config(config)?;
+        *cached = Some((config.clone(), backend.clone()));
+        Ok(backend)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Failing(fn() -> GogglesError);
+
+    #[async_trait]
+    impl NamingBackend for Failing {
+        fn id(&self) -> &str {
+            "failing"
+        }
+
+        async fn suggest_name(
+            &self,
+            _path: &Path,
+            _context: &NamingContext,
+        ) -> Result<NameSuggestion, anyhow::Error> {
+            Err((self.0)().into())
+        }
+    }
+
+    async fn suggest(error: fn() -> GogglesError) -> Result<NameSuggestion, anyhow::Error> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shot.png");
+        image::RgbImage::new(8, 8).save(&path).unwrap();
+
+        FallbackBackend::new(Arc::new(Failing(error)), Arc::new(LocalBackend::new()))
+            .suggest_name(&path, &NamingContext::default())
+            .await
+    }
+
+    #[tokio::test]
+    async fn falls_back_on_network_errors() {
+        let suggestion = suggest(|| {
+            let invalid = reqwest::Client::new().get("not a url").build();
+            GogglesError::Network(invalid.unwrap_err())
+        })
+        .await
+        .unwrap();
+        assert_eq!(suggestion.backend, "local");
+    }
+
+    #[tokio::test]
+    async fn names_files_that_are_not_images_locally() {
+        let suggestion = suggest(|| GogglesError::NotAnImage("report.pdf".into()))
+            .await
+            .unwrap();
+        assert_eq!(suggestion.backend, "local");
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn reuses_the_backend_until_its_settings_change() {
+        let cache = BackendCache::default();
+        let config = GogglesConfig::default();
+        let first = cache.get(&config).unwrap();
+
+        let touched = GogglesConfig {
+            updated_at: config.updated_at + 60,
+            name_template: "{date}-{ai}".to_string(),
+            ..config.clone()
+        };
+        assert!(Arc::ptr_eq(&first, &cache.get(&touched).unwrap()));
+
+        let moved = GogglesConfig {
+            server_url: "http://127.0.0.1:1".to_string(),
+            ..config
+        };
+        assert!(!Arc::ptr_eq(&first, &cache.get(&moved).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn waits_out_a_paused_server_instead_of_falling_back() {
+        let error = suggest(|| GogglesError::ServerUnavailable { retry_in_secs: 30 })
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<GogglesError>(),
+            Some(GogglesError::ServerUnavailable { .. })
+        ));
+    }
+}
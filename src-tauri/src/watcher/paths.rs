@@ -0,0 +1,271 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "goggles";
+const HOME_OVERRIDE_VAR: &str = "GOGGLES_HOME";
+
+/// Every directory Goggles writes to, resolved in one place.
+///
+/// `GOGGLES_HOME` puts everything under one directory. Otherwise Linux
+/// follows the XDG base directories, while macOS (and Linux installs that
+/// already have one) keep config and state in `~/.goggles`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPaths {
+    pub config_dir: PathBuf,
+    pub state_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub log_dir: PathBuf,
+    pub runtime_dir: PathBuf,
+}
+
+impl AppPaths {
+    /// Resolves the directories and creates any that are missing.
+    pub fn new() -> Result<Self, anyhow::Error> {
+        let paths = Self::resolve()?;
+        for dir in [
+            &paths.config_dir,
+            &paths.state_dir,
+            &paths.cache_dir,
+            &paths.log_dir,
+            &paths.runtime_dir,
+        ] {
+            ensure_dir(dir)?;
+        }
+        Ok(paths)
+    }
+
+    /// Resolves the directories without touching the filesystem.
+    pub fn resolve() -> Result<Self, anyhow::Error> {
+        Self::resolve_from(
+            home_override(),
+            dirs::home_dir().as_deref(),
+            &BaseDirs::from_platform(),
+        )
+    }
+
+    fn resolve_from(
+        root: Option<PathBuf>,
+        home: Option<&Path>,
+        base: &BaseDirs,
+    ) -> Result<Self, anyhow::Error> {
+        if let Some(root) = root {
+            return Ok(Self::under(root));
+        }
+
+        let home = home.ok_or_else(|| anyhow::anyhow!("Could not determine the home directory"))?;
+        let legacy = home.join(".goggles");
+
+        if cfg!(target_os = "linux") && !legacy.exists() {
+            let config_dir = base
+                .config
+                .clone()
+                .unwrap_or_else(|| home.join(".config"))
+                .join(APP_DIR);
+            let state_dir = base
+                .state
+                .clone()
+                .unwrap_or_else(|| home.join(".local").join("state"))
+                .join(APP_DIR);
+            let cache_dir = base
+                .cache
+                .clone()
+                .unwrap_or_else(|| home.join(".cache"))
+                .join(APP_DIR);
+            let runtime_dir = base
+                .runtime
+                .as_ref()
+                .map(|dir| dir.join(APP_DIR))
+                .unwrap_or_else(|| state_dir.clone());
+            return Ok(Self {
+                log_dir: state_dir.join("logs"),
+                config_dir,
+                state_dir,
+                cache_dir,
+                runtime_dir,
+            });
+        }
+
+        let cache_dir = base
+            .cache
+            .as_ref()
+            .map(|dir| dir.join(APP_DIR))
+            .unwrap_or_else(|| legacy.join("cache"));
+        let log_dir = if cfg!(target_os = "macos") {
+            home.join("Library").join("Logs").join(APP_DIR)
+        } else {
+            legacy.join("logs")
+        };
+        Ok(Self {
+            config_dir: legacy.clone(),
+            state_dir: legacy.clone(),
+            cache_dir,
+            log_dir,
+            runtime_dir: legacy,
+        })
+    }
+
+    fn under(root: PathBuf) -> Self {
+        Self {
+            config_dir: root.clone(),
+            state_dir: root.join("state"),
+            cache_dir: root.join("cache"),
+            log_dir: root.join("logs"),
+            runtime_dir: root.join("run"),
+        }
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config_dir.join("config.json")
+    }
+
+    pub fn journal_file(&self) -> PathBuf {
+        self.state_dir.join("journal.jsonl")
+    }
+
+    pub fn pending_file(&self) -> PathBuf {
+        self.state_dir.join("pending.json")
+    }
+
+    pub fn pid_file(&self) -> PathBuf {
+        self.runtime_dir.join("goggles.pid")
+    }
+}
+
+/// The platform base directories `resolve` builds on, from `dirs`.
+#[derive(Debug, Clone, Default)]
+struct BaseDirs {
+    config: Option<PathBuf>,
+    state: Option<PathBuf>,
+    cache: Option<PathBuf>,
+    runtime: Option<PathBuf>,
+}
+
+impl BaseDirs {
+    fn from_platform() -> Self {
+        Self {
+            config: dirs::config_dir(),
+            state: dirs::state_dir(),
+            cache: dirs::cache_dir(),
+            runtime: dirs::runtime_dir(),
+        }
+    }
+}
+
+/// The `GOGGLES_HOME` directory, when set.
+pub fn home_override() -> Option<PathBuf> {
+    override_root(std::env::var_os(HOME_OVERRIDE_VAR))
+}
+
+fn override_root(value: Option<OsString>) -> Option<PathBuf> {
+    value.filter(|root| !root.is_empty()).map(PathBuf::from)
+}
+
+/// Creates `dir` readable only by the current user.
+fn ensure_dir(dir: &Path) -> Result<(), anyhow::Error> {
+    if dir.exists() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir).map_err(|e| {
+        anyhow::Error::new(e).context(format!("Failed to create {}", dir.display()))
+    })?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_dirs(root: &Path) -> BaseDirs {
+        BaseDirs {
+            config: Some(root.join("xdg-config")),
+            state: Some(root.join("xdg-state")),
+            cache: Some(root.join("xdg-cache")),
+            runtime: Some(root.join("xdg-runtime")),
+        }
+    }
+
+    #[test]
+    fn goggles_home_puts_everything_under_one_directory() {
+        let root = override_root(Some(OsString::from("/srv/goggles"))).unwrap();
+        let paths = AppPaths::resolve_from(Some(root), None, &BaseDirs::default()).unwrap();
+
+        assert_eq!(
+            paths,
+            AppPaths {
+                config_dir: PathBuf::from("/srv/goggles"),
+                state_dir: PathBuf::from("/srv/goggles/state"),
+                cache_dir: PathBuf::from("/srv/goggles/cache"),
+                log_dir: PathBuf::from("/srv/goggles/logs"),
+                runtime_dir: PathBuf::from("/srv/goggles/run"),
+            }
+        );
+        assert_eq!(paths.config_file(), Path::new("/srv/goggles/config.json"));
+        assert_eq!(override_root(Some(OsString::new())), None);
+        assert_eq!(override_root(None), None);
+    }
+
+    #[test]
+    fn needs_a_home_without_an_override() {
+        assert!(AppPaths::resolve_from(None, None, &BaseDirs::default()).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn linux_follows_xdg_without_a_legacy_directory() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+
+        let paths = AppPaths::resolve_from(None, Some(home), &base_dirs(home)).unwrap();
+        assert_eq!(paths.config_dir, home.join("xdg-config/goggles"));
+        assert_eq!(paths.state_dir, home.join("xdg-state/goggles"));
+        assert_eq!(paths.cache_dir, home.join("xdg-cache/goggles"));
+        assert_eq!(paths.log_dir, home.join("xdg-state/goggles/logs"));
+        assert_eq!(paths.runtime_dir, home.join("xdg-runtime/goggles"));
+
+        // falls back to the spec defaults, and state for runtime files
+        let paths = AppPaths::resolve_from(None, Some(home), &BaseDirs::default()).unwrap();
+        assert_eq!(paths.config_dir, home.join(".config/goggles"));
+        assert_eq!(paths.cache_dir, home.join(".cache/goggles"));
+        assert_eq!(paths.runtime_dir, home.join(".local/state/goggles"));
+    }
+
+    #[test]
+    fn keeps_an_existing_legacy_directory() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+        let legacy = home.join(".goggles");
+        fs::create_dir(&legacy).unwrap();
+
+        let paths = AppPaths::resolve_from(None, Some(home), &base_dirs(home)).unwrap();
+        assert_eq!(paths.config_dir, legacy);
+        assert_eq!(paths.state_dir, legacy);
+        assert_eq!(paths.runtime_dir, legacy);
+        assert_eq!(paths.cache_dir, home.join("xdg-cache/goggles"));
+        assert_eq!(paths.journal_file(), legacy.join("journal.jsonl"));
+        if cfg!(target_os = "linux") {
+            assert_eq!(paths.log_dir, legacy.join("logs"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn creates_directories_private_to_the_user() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let paths = AppPaths::under(dir.path().join("goggles"));
+        for dir in [&paths.config_dir, &paths.state_dir, &paths.runtime_dir] {
+            ensure_dir(dir).unwrap();
+            let mode = fs::metadata(dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700, "{}", dir.display());
+        }
+    }
+}
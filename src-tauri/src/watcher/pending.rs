@@ -0,0 +1,243 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::watcher::fsops::write_atomic;
+use crate::watcher::paths::AppPaths;
+
+/// Failed attempts after which a file is given up on.
+const MAX_ATTEMPTS: u32 = 3;
+
+// queue workers finish concurrently, serialize file access
+static PENDING_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingJob {
+    pub path: PathBuf,
+    pub screenshot: bool,
+    pub queued_at: u64,
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// Files queued for renaming but not done yet, kept on disk so they are
+/// picked up again after a restart.
+#[derive(Debug, Clone)]
+pub struct PendingJobs {
+    path: PathBuf,
+}
+
+impl PendingJobs {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The pending list in the app state directory.
+    pub fn open() -> Result<Self, anyhow::Error> {
+        Ok(Self::new(AppPaths::new()?.pending_file()))
+    }
+
+    /// The pending list. One that can't be parsed, e.g. cut short by a
+    /// crash, is moved aside and the list starts empty.
+    fn read(&self) -> Result<Vec<PendingJob>, anyhow::Error> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let content = fs::read(&self.path)?;
+        match serde_json::from_slice(&content) {
+            Ok(jobs) => Ok(jobs),
+            Err(e) => {
+                let mut corrupt_name = self.path.file_name().unwrap_or_default().to_os_string();
+                corrupt_name.push(format!(".corrupt-{}", now()));
+                let corrupt_path = self.path.with_file_name(corrupt_name);
+                error!(
+                    "Unreadable pending jobs in {}, moving them to {}: {}",
+                    self.path.display(),
+                    corrupt_path.display(),
+                    e
+                );
+                fs::rename(&self.path, &corrupt_path)?;
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// Replaces the list through a temp file, so a failed write leaves the
+    /// old one intact.
+    fn write(&self, jobs: &[PendingJob]) -> Result<(), anyhow::Error> {
+        let content = serde_json::to_string_pretty(jobs)?;
+        write_atomic(&self.path, content.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<PendingJob>, anyhow::Error> {
+        let _guard = PENDING_LOCK.lock().unwrap();
+        self.read()
+    }
+
+    /// Adds `path` unless it is already pending.
+    pub fn add(&self, path: &Path, screenshot: bool) -> Result<(), anyhow::Error> {
+        let _guard = PENDING_LOCK.lock().unwrap();
+
+        let mut jobs = self.read()?;
+        if jobs.iter().any(|job| job.path == path) {
+            return Ok(());
+        }
+        jobs.push(PendingJob {
+            path: path.to_path_buf(),
+            screenshot,
+            queued_at: now(),
+            attempts: 0,
+        });
+        self.write(&jobs)
+    }
+
+    pub fn remove(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let _guard = PENDING_LOCK.lock().unwrap();
+
+        let mut jobs = self.read()?;
+        let before = jobs.len();
+        jobs.retain(|job| job.path != path);
+        if jobs.len() != before {
+            self.write(&jobs)?;
+        }
+        Ok(())
+    }
+
+    /// Counts a failed attempt. Returns false once the file was dropped,
+    /// because it is gone or failed too often.
+    pub fn record_failure(&self, path: &Path) -> Result<bool, anyhow::Error> {
+        let _guard = PENDING_LOCK.lock().unwrap();
+
+        let mut jobs = self.read()?;
+        let Some(index) = jobs.iter().position(|job| job.path == path) else {
+            return Ok(false);
+        };
+
+        jobs[index].attempts += 1;
+        let keep = path.exists() && jobs[index].attempts < MAX_ATTEMPTS;
+        if !keep {
+            jobs.remove(index);
+        }
+        self.write(&jobs)?;
+        Ok(keep)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending() -> (tempfile::TempDir, PendingJobs) {
+        let dir = tempfile::tempdir().unwrap();
+        let pending = PendingJobs::new(dir.path().join("pending.json"));
+        (dir, pending)
+    }
+
+    fn paths(pending: &PendingJobs) -> Vec<PathBuf> {
+        pending
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|job| job.path)
+            .collect()
+    }
+
+    #[test]
+    fn adds_each_file_once() {
+        let (dir, pending) = pending();
+        let shot = dir.path().join("shot.png");
+        let report = dir.path().join("report.pdf");
+
+        pending.add(&shot, true).unwrap();
+        pending.add(&report, false).unwrap();
+        pending.add(&shot, false).unwrap();
+
+        let jobs = pending.list().unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].path, shot);
+        assert!(jobs[0].screenshot);
+        assert_eq!(jobs[0].attempts, 0);
+        assert_eq!(jobs[1].path, report);
+    }
+
+    #[test]
+    fn removes_finished_files() {
+        let (dir, pending) = pending();
+        let shot = dir.path().join("shot.png");
+        let report = dir.path().join("report.pdf");
+        pending.add(&shot, true).unwrap();
+        pending.add(&report, false).unwrap();
+
+        pending.remove(&shot).unwrap();
+        assert_eq!(paths(&pending), vec![report.clone()]);
+        // removing something that isn't there is fine
+        pending.remove(&shot).unwrap();
+        assert_eq!(paths(&pending), vec![report]);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let (dir, pending) = pending();
+        let shot = dir.path().join("shot.png");
+        fs::write(&shot, b"png").unwrap();
+        pending.add(&shot, true).unwrap();
+
+        for attempt in 1..MAX_ATTEMPTS {
+            assert!(pending.record_failure(&shot).unwrap());
+            assert_eq!(pending.list().unwrap()[0].attempts, attempt);
+        }
+        assert!(!pending.record_failure(&shot).unwrap());
+        assert!(paths(&pending).is_empty());
+        // an unknown file is not pending either
+        assert!(!pending.record_failure(&shot).unwrap());
+    }
+
+    #[test]
+    fn drops_files_that_are_gone() {
+        let (dir, pending) = pending();
+        let gone = dir.path().join("gone.png");
+        pending.add(&gone, true).unwrap();
+
+        assert!(!pending.record_failure(&gone).unwrap());
+        assert!(paths(&pending).is_empty());
+    }
+
+    #[test]
+    fn moves_a_corrupt_list_aside() {
+        let (dir, pending) = pending();
+        let torn = br#"[{"path": "/watched/shot.png", "screen"#;
+        fs::write(dir.path().join("pending.json"), torn).unwrap();
+
+        assert!(pending.list().unwrap().is_empty());
+        let shot = dir.path().join("shot.png");
+        pending.add(&shot, true).unwrap();
+        assert_eq!(paths(&pending), vec![shot]);
+
+        let aside: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with("pending.json.corrupt-"))
+            .collect();
+        assert_eq!(aside.len(), 1);
+        assert_eq!(fs::read(dir.path().join(&aside[0])).unwrap(), torn);
+    }
+
+    #[test]
+    fn leaves_no_temp_file_behind() {
+        let (dir, pending) = pending();
+        pending.add(&dir.path().join("shot.png"), true).unwrap();
+        assert!(!dir.path().join("pending.json.tmp").exists());
+    }
+}
@@ -1,14 +1,135 @@
-use std::path::PathBuf;
+use std::fmt;
+use std::fs::{File, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-fn get_pid_path() -> PathBuf {
-    let parent_path = dirs::config_dir().unwrap().join("goggles");
-    if !parent_path.exists() {
-        std::fs::create_dir_all(parent_path.clone()).unwrap();
+use log::{info, warn};
+
+use crate::watcher::paths::AppPaths;
+
+// held for the lifetime of the process, dropping it releases the lock
+static PID_LOCK: Mutex<Option<PidLock>> = Mutex::new(None);
+
+/// Returned by `acquire` when another Goggles process holds the PID file.
+#[derive(Debug)]
+pub struct AlreadyRunning {
+    pub pid: Option<u32>,
+}
+
+impl fmt::Display for AlreadyRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "Goggles is already running with PID {}", pid),
+            None => write!(f, "Goggles is already running"),
+        }
+    }
+}
+
+impl std::error::Error for AlreadyRunning {}
+
+struct PidLock {
+    // keeps the exclusive lock alive
+    file: File,
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        // clear the PID but keep the file: unlinking it while locked lets
+        // a new process lock a fresh file while another still holds the
+        // old one, and both would run
+        let _ = self.file.set_len(0);
+    }
+}
+
+fn get_pid_path() -> Result<PathBuf, anyhow::Error> {
+    Ok(AppPaths::new()?.pid_file())
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+/// Makes this process the single running instance.
+///
+/// Takes an exclusive lock on the PID file and writes our PID into it. The
+/// OS drops the lock when a process dies, so a PID left behind by a crash
+/// is simply replaced. Fails with `AlreadyRunning` while another live
+/// process holds the lock.
+pub fn acquire() -> Result<(), anyhow::Error> {
+    let lock = lock_pid_file(&get_pid_path()?)?;
+    *PID_LOCK.lock().unwrap() = Some(lock);
+    Ok(())
+}
+
+fn lock_pid_file(pid_path: &Path) -> Result<PidLock, anyhow::Error> {
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(pid_path)?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            return Err(AlreadyRunning {
+                pid: read_pid(&mut file),
+            }
+            .into());
+        }
+        Err(TryLockError::Error(e)) => return Err(e.into()),
     }
-    parent_path.join("goggles.pid")
+
+    let pid = std::process::id();
+    if let Some(stale) = read_pid(&mut file).filter(|stale| *stale != pid) {
+        warn!("Replacing stale PID file left by process {}", stale);
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", pid)?;
+    file.sync_all()?;
+    info!("Wrote PID {} to {}", pid, pid_path.display());
+
+    Ok(PidLock { file })
 }
 
-pub fn save_pid(pid: u32) {
-    let pid_path = get_pid_path();
-    std::fs::write(pid_path, pid.to_string()).unwrap();
+/// Clears the PID file and releases the lock, call before a clean exit.
+pub fn release() {
+    PID_LOCK.lock().unwrap().take();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_lock_reports_the_running_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("goggles.pid");
+
+        let _lock = lock_pid_file(&path).unwrap();
+        let err = lock_pid_file(&path).err().unwrap();
+        let running = err.downcast_ref::<AlreadyRunning>().unwrap();
+        assert_eq!(running.pid, Some(std::process::id()));
+    }
+
+    #[test]
+    fn release_keeps_the_file_and_clears_the_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("goggles.pid");
+
+        drop(lock_pid_file(&path).unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        // the next process locks the same file
+        let _lock = lock_pid_file(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+    }
 }
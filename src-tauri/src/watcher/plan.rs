@@ -0,0 +1,17 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// What `SSManager::plan_rename` would do with a file, without doing it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RenamePlan {
+    pub old_path: PathBuf,
+    /// `None` when the file would be left alone, see `reason`.
+    pub new_path: Option<PathBuf>,
+    /// The suggested path, when another file already has it.
+    pub collision: Option<PathBuf>,
+    /// Backend that suggested the name, `template` if none was asked.
+    pub backend: String,
+    /// Human readable explanation of the proposed outcome.
+    pub reason: String,
+}
@@ -0,0 +1,228 @@
+use std::path::Path;
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::watcher::naming::NamingContext;
+
+/// Part of a screenshot covered before upload, e.g. the menu bar. Given as
+/// fractions of the image so it holds across display resolutions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct RedactRegion {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl RedactRegion {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let in_range = |value: f32| (0.0..=1.0).contains(&value);
+        if !(in_range(self.x) && in_range(self.y) && in_range(self.width) && in_range(self.height))
+            || self.width == 0.0
+            || self.height == 0.0
+            || self.x + self.width > 1.0
+            || self.y + self.height > 1.0
+        {
+            return Err(anyhow::anyhow!(
+                "Redact region {:?} must lie within 0..1 of the image",
+                self
+            ));
+        }
+        Ok(())
+    }
+
+    /// Pixel bounds of the region in a `width` x `height` image.
+    fn bounds(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let x = (self.x * width as f32).floor() as u32;
+        let y = (self.y * height as f32).floor() as u32;
+        let right = ((self.x + self.width) * width as f32).ceil() as u32;
+        let bottom = ((self.y + self.height) * height as f32).ceil() as u32;
+        (
+            x.min(width),
+            y.min(height),
+            right.min(width).saturating_sub(x),
+            bottom.min(height).saturating_sub(y),
+        )
+    }
+}
+
+/// Covers every region of `image` in place with its average colour. A
+/// blur can leave large text readable, a single colour can't.
+pub fn redact(image: &mut DynamicImage, regions: &[RedactRegion]) {
+    for region in regions {
+        let (x, y, width, height) = region.bounds(image.width(), image.height());
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let fill = average_colour(&image.crop_imm(x, y, width, height).to_rgba8());
+        let patch = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, fill));
+        imageops::replace(image, &patch, x as i64, y as i64);
+    }
+}
+
+fn average_colour(image: &RgbaImage) -> Rgba<u8> {
+    let mut sums = [0u64; 4];
+    for pixel in image.pixels() {
+        for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+            *sum += channel as u64;
+        }
+    }
+    let count = (image.width() as u64 * image.height() as u64).max(1);
+    Rgba(sums.map(|sum| (sum / count) as u8))
+}
+
+/// Glob patterns marking files that must never leave the device. They are
+/// matched against the filename and, when known, the app and window title
+/// that were frontmost when the file was created.
+#[derive(Debug, Clone, Default)]
+pub struct PrivacyRules {
+    patterns: Vec<glob::Pattern>,
+}
+
+impl PrivacyRules {
+    pub fn new(rules: &[String]) -> Self {
+        let patterns = rules
+            .iter()
+            .filter_map(|rule| match glob::Pattern::new(rule) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    error!("Invalid privacy rule {:?}: {}", rule, e);
+                    None
+                }
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    /// The rule that keeps `path` on the device, if any.
+    pub fn matching(&self, path: &Path, context: &NamingContext) -> Option<&str> {
+        let filename = path.file_name().and_then(|name| name.to_str());
+        let app = context
+            .frontmost
+            .as_ref()
+            .map(|window| window.app_name.as_str());
+        let title = context
+            .frontmost
+            .as_ref()
+            .and_then(|window| window.window_title.as_deref());
+
+        self.patterns
+            .iter()
+            .find(|pattern| {
+                [filename, app, title]
+                    .into_iter()
+                    .flatten()
+                    .any(|value| pattern.matches(value))
+            })
+            .map(|pattern| pattern.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher::macos::FrontmostWindow;
+
+    fn region(x: f32, y: f32, width: f32, height: f32) -> RedactRegion {
+        RedactRegion {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Every pixel different, like text on a page.
+    fn noisy(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 7 + y * 13) as u8, (x * 31) as u8, (y * 17) as u8, 255])
+        }))
+    }
+
+    #[test]
+    fn covers_the_region_with_one_colour() {
+        let original = noisy(20, 10);
+        let mut image = original.clone();
+        redact(&mut image, &[region(0.5, 0.0, 0.5, 0.5)]);
+
+        let (before, after) = (original.to_rgba8(), image.to_rgba8());
+        let fill = *after.get_pixel(10, 0);
+        for (x, y, pixel) in after.enumerate_pixels() {
+            if x >= 10 && y < 5 {
+                assert_eq!(*pixel, fill, "({}, {}) still shows the original", x, y);
+            } else {
+                assert_eq!(pixel, before.get_pixel(x, y), "({}, {}) changed", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_cover_partial_pixels() {
+        assert_eq!(region(0.33, 0.0, 0.34, 1.0).bounds(10, 4), (3, 0, 4, 4));
+        // a sliver still covers a whole pixel
+        assert_eq!(region(0.0, 0.0, 0.001, 0.001).bounds(10, 4), (0, 0, 1, 1));
+        assert_eq!(region(0.5, 0.5, 0.5, 0.5).bounds(11, 5), (5, 2, 6, 3));
+        assert_eq!(region(0.0, 0.0, 1.0, 1.0).bounds(0, 0), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn validates_regions() {
+        assert!(region(0.0, 0.0, 1.0, 0.05).validate().is_ok());
+        assert!(region(0.9, 0.9, 0.1, 0.1).validate().is_ok());
+        for invalid in [
+            region(-0.1, 0.0, 0.5, 0.5),
+            region(0.0, 0.0, 0.0, 0.5),
+            region(0.0, 0.0, 0.5, 0.0),
+            region(0.6, 0.0, 0.5, 0.5),
+            region(0.0, 0.6, 0.5, 0.5),
+            region(f32::NAN, 0.0, 0.5, 0.5),
+        ] {
+            assert!(invalid.validate().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn rules_match_filename_app_and_title() {
+        let rules = PrivacyRules::new(&[
+            "*bank*".to_string(),
+            "1Password*".to_string(),
+            "*Private Browsing*".to_string(),
+            "[invalid".to_string(),
+        ]);
+        let path = Path::new("/shots/Screenshot.png");
+        let context = |app: &str, title: Option<&str>| NamingContext {
+            address: String::new(),
+            frontmost: Some(FrontmostWindow {
+                app_name: app.to_string(),
+                window_title: title.map(str::to_string),
+            }),
+            screenshot: true,
+        };
+
+        assert_eq!(
+            rules.matching(
+                Path::new("/shots/bank-statement.png"),
+                &NamingContext::default()
+            ),
+            Some("*bank*")
+        );
+        assert_eq!(
+            rules.matching(path, &context("1Password 8", None)),
+            Some("1Password*")
+        );
+        assert_eq!(
+            rules.matching(
+                path,
+                &context("Firefox", Some("Private Browsing - Firefox"))
+            ),
+            Some("*Private Browsing*")
+        );
+        assert_eq!(
+            rules.matching(path, &context("Finder", Some("Downloads"))),
+            None
+        );
+        assert_eq!(rules.matching(path, &NamingContext::default()), None);
+    }
+}
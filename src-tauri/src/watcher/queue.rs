@@ -0,0 +1,556 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, info, warn};
+use serde::Serialize;
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::watcher::error::GogglesError;
+use crate::watcher::image::SSManager;
+use crate::watcher::local::LocalBackend;
+use crate::watcher::naming::NamingContext;
+use crate::watcher::pending::PendingJobs;
+use crate::watcher::retry::{CircuitBreaker, SERVER_CIRCUIT};
+
+/// Finished jobs kept around for `job_statuses`.
+const MAX_FINISHED_JOBS: usize = 100;
+/// Times a job waits for a paused server before it is named locally.
+const MAX_REQUEUES: u32 = 1;
+
+static NEXT_JOB_ID: AtomicU64 = AtomicU64::new(1);
+
+// shared by every queue in the process so the UI sees one list
+static JOB_BOARD: Mutex<VecDeque<JobInfo>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done { new_path: PathBuf },
+    Failed { error: String },
+}
+
+impl JobStatus {
+    fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Done { .. } | JobStatus::Failed { .. })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub path: PathBuf,
+    pub status: JobStatus,
+    pub queued_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+/// A file waiting to be renamed by `manager`.
+pub struct Job {
+    pub path: PathBuf,
+    pub context: NamingContext,
+    pub manager: SSManager,
+    /// Use the screenshot checks of `process_new_ss`.
+    pub screenshot: bool,
+}
+
+/// Bounded queue feeding a pool of at most `concurrency` running jobs.
+/// `submit` waits while the queue is full. With `pending` set, jobs are
+/// kept on disk until they are done.
+#[derive(Clone)]
+pub struct JobQueue {
+    sender: mpsc::Sender<(u64, Job)>,
+    concurrency: usize,
+    capacity: usize,
+    pending: Option<PendingJobs>,
+}
+
+impl JobQueue {
+    pub fn new(concurrency: usize, capacity: usize, pending: Option<PendingJobs>) -> Self {
+        Self::with_circuit(concurrency, capacity, pending, &SERVER_CIRCUIT)
+    }
+
+    /// Like `new`, pausing on `circuit` instead of `SERVER_CIRCUIT`.
+    pub fn with_circuit(
+        concurrency: usize,
+        capacity: usize,
+        pending: Option<PendingJobs>,
+        circuit: &'static CircuitBreaker,
+    ) -> Self {
+        let concurrency = concurrency.max(1);
+        let capacity = capacity.max(1);
+        let (sender, receiver) = mpsc::channel(capacity);
+        tokio::spawn(dispatch(receiver, concurrency, pending.clone(), circuit));
+        Self {
+            sender,
+            concurrency,
+            capacity,
+            pending,
+        }
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Queues `job` unless the same file is already waiting or running.
+    pub async fn submit(&self, job: Job) -> Option<u64> {
+        let id = {
+            let mut board = JOB_BOARD.lock().unwrap();
+            if board
+                .iter()
+                .any(|info| info.path == job.path && !info.status.is_finished())
+            {
+                info!("Already queued: {:?}", job.path);
+                return None;
+            }
+
+            let id = NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed);
+            board.push_back(JobInfo {
+                id,
+                path: job.path.clone(),
+                status: JobStatus::Queued,
+                queued_at: now(),
+                finished_at: None,
+            });
+            id
+        };
+
+        if let Some(pending) = &self.pending {
+            if let Err(e) = pending.add(&job.path, job.screenshot) {
+                error!("Failed to persist pending job: {:?}", e);
+            }
+        }
+
+        if self.sender.capacity() == 0 {
+            warn!("Job queue is full, waiting for a free slot");
+        }
+        if self.sender.send((id, job)).await.is_err() {
+            error!("Job queue is closed, dropping job {}", id);
+            set_status(
+                id,
+                JobStatus::Failed {
+                    error: "Job queue is closed".to_string(),
+                },
+            );
+            return None;
+        }
+        Some(id)
+    }
+}
+
+/// Latest known state of every queued, running and recently finished job.
+#[cfg(feature = "gui")]
+pub fn job_statuses() -> Vec<JobInfo> {
+    JOB_BOARD.lock().unwrap().iter().cloned().collect()
+}
+
+async fn dispatch(
+    mut receiver: mpsc::Receiver<(u64, Job)>,
+    concurrency: usize,
+    pending: Option<PendingJobs>,
+    circuit: &'static CircuitBreaker,
+) {
+    let permits = Arc::new(Semaphore::new(concurrency));
+    loop {
+        // only take the next job once a worker is free, so a full pool
+        // backs up into the bounded channel
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            break;
+        };
+        let Some((id, mut job)) = receiver.recv().await else {
+            break;
+        };
+        // hold jobs back while the server is down instead of burning
+        // through them with fallback names
+        if let Some(remaining) = circuit.remaining() {
+            info!(
+                "Server unavailable, pausing queue for {}s",
+                remaining.as_secs()
+            );
+            circuit.wait_until_closed().await;
+        }
+
+        let pending = pending.clone();
+        tokio::spawn(async move {
+            let mut requeues = 0;
+            let result = loop {
+                set_status(id, JobStatus::Running);
+                let result = if job.screenshot {
+                    job.manager.process_new_ss(&job.context, &job.path).await
+                } else {
+                    job.manager.process_new_file(&job.context, &job.path).await
+                };
+                // the circuit opened while this job was running, run it
+                // again once the server is back
+                if !is_server_unavailable(&result) {
+                    break result;
+                }
+                if requeues == MAX_REQUEUES {
+                    // the server is still down after a full pause, don't
+                    // hold the file back any longer
+                    info!("Server still unavailable, naming {:?} locally", job.path);
+                    job.manager = job.manager.with_backend(Arc::new(LocalBackend::new()));
+                    continue;
+                }
+                requeues += 1;
+                info!("Server unavailable, requeueing {:?}", job.path);
+                set_status(id, JobStatus::Queued);
+                circuit.wait_until_closed().await;
+            };
+            if let Some(pending) = &pending {
+                let persisted = match &result {
+                    Ok(_) => pending.remove(&job.path),
+                    // keep it for the next start unless it failed for good
+                    Err(_) => pending.record_failure(&job.path).map(|_| ()),
+                };
+                if let Err(e) = persisted {
+                    error!("Failed to update pending jobs: {:?}", e);
+                }
+            }
+
+            match result {
+                Ok(new_path) => set_status(id, JobStatus::Done { new_path }),
+                Err(e) => {
+                    error!("Error processing file: {:?}", e);
+                    set_status(
+                        id,
+                        JobStatus::Failed {
+                            error: e.to_string(),
+                        },
+                    );
+                }
+            }
+            drop(permit);
+        });
+    }
+}
+
+fn is_server_unavailable<T>(result: &Result<T, anyhow::Error>) -> bool {
+    matches!(
+        result
+            .as_ref()
+            .map_err(|e| e.downcast_ref::<GogglesError>()),
+        Err(Some(GogglesError::ServerUnavailable { .. }))
+    )
+}
+
+fn set_status(id: u64, status: JobStatus) {
+    let mut board = JOB_BOARD.lock().unwrap();
+    let finished = status.is_finished();
+    if let Some(info) = board.iter_mut().find(|info| info.id == id) {
+        info.status = status;
+        if finished {
+            info.finished_at = Some(now());
+        }
+    }
+
+    // forget the oldest finished jobs, never the pending ones
+    let mut finished_count = board
+        .iter()
+        .filter(|info| info.status.is_finished())
+        .count();
+    board.retain(|info| {
+        if finished_count > MAX_FINISHED_JOBS && info.status.is_finished() {
+            finished_count -= 1;
+            return false;
+        }
+        true
+    });
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use crate::watcher::ai::OpenAI;
+    use crate::watcher::journal::RenameJournal;
+    use crate::watcher::mock_server::mock_server;
+    use crate::watcher::naming::{FallbackBackend, NameSuggestion, NamingBackend};
+
+    static CIRCUIT: CircuitBreaker = CircuitBreaker::new(2, Duration::from_millis(200));
+    // never opened, the gated backend doesn't fail
+    static CLOSED: CircuitBreaker = CircuitBreaker::new(100, Duration::from_millis(200));
+
+    /// Names files once `gate` lets it, counting how many wait at once.
+    struct Gated {
+        gate: Semaphore,
+        running: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl Gated {
+        fn open(permits: usize) -> Arc<Self> {
+            Arc::new(Self {
+                gate: Semaphore::new(permits),
+                running: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl NamingBackend for Gated {
+        fn id(&self) -> &str {
+            "gated"
+        }
+
+        async fn suggest_name(
+            &self,
+            path: &Path,
+            _context: &NamingContext,
+        ) -> Result<NameSuggestion, anyhow::Error> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            let permit = self.gate.acquire().await?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(permit);
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            Ok(NameSuggestion {
+                name: format!("named {}", stem),
+                backend: self.id().to_string(),
+            })
+        }
+    }
+
+    fn manager(dir: &Path, backend: Arc<Gated>) -> SSManager {
+        SSManager::new(backend, RenameJournal::new(dir.join("journal.jsonl")))
+    }
+
+    fn job(dir: &Path, name: &str, manager: &SSManager) -> Job {
+        let path = dir.join(name);
+        image::RgbImage::new(8, 8).save(&path).unwrap();
+        Job {
+            path,
+            context: NamingContext::default(),
+            manager: manager.clone(),
+            screenshot: false,
+        }
+    }
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("condition never held");
+    }
+
+    fn status(id: u64) -> Option<JobStatus> {
+        job_info(id).map(|info| info.status)
+    }
+
+    fn job_info(id: u64) -> Option<JobInfo> {
+        JOB_BOARD
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|info| info.id == id)
+            .cloned()
+    }
+
+    #[tokio::test]
+    async fn failing_server_does_not_stall_the_queue() {
+        let (url, requests) =
+            mock_server(vec![(500, r#"{"error":"Failed to generate filename"}"#)]);
+        let dir = tempfile::tempdir().unwrap();
+        let backend = FallbackBackend::new(
+            Arc::new(OpenAI::for_test(&url, &CIRCUIT)),
+            Arc::new(LocalBackend::new()),
+        );
+        let manager = SSManager::new(
+            Arc::new(backend),
+            RenameJournal::new(dir.path().join("journal.jsonl")),
+        );
+        let queue = JobQueue::with_circuit(1, 10, None, &CIRCUIT);
+
+        let mut ids = Vec::new();
+        for size in 1..=3 {
+            let path = dir.path().join(format!("image-{}.png", size));
+            image::RgbImage::new(size * 8, 8).save(&path).unwrap();
+            let id = queue
+                .submit(Job {
+                    path,
+                    context: NamingContext::default(),
+                    manager: manager.clone(),
+                    screenshot: false,
+                })
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+
+        tokio::time::timeout(Duration::from_secs(20), async {
+            while !ids
+                .iter()
+                .all(|id| status(*id).is_some_and(|status| status.is_finished()))
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("queue stalled on the failing server");
+
+        for id in ids {
+            assert!(
+                matches!(status(id), Some(JobStatus::Done { .. })),
+                "job {} was not named locally",
+                id
+            );
+        }
+        // two requests open the circuit, then one failed probe per job
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn runs_at_most_concurrency_jobs_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = Gated::open(Semaphore::MAX_PERMITS);
+        let manager = manager(dir.path(), backend.clone());
+        let queue = JobQueue::with_circuit(2, 10, None, &CLOSED);
+
+        let mut ids = Vec::new();
+        for index in 0..6 {
+            let job = job(dir.path(), &format!("image-{}.png", index), &manager);
+            ids.push(queue.submit(job).await.unwrap());
+        }
+        wait_until(|| {
+            ids.iter()
+                .all(|id| status(*id).is_some_and(|status| status.is_finished()))
+        })
+        .await;
+
+        assert_eq!(backend.peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_holds_back_submissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = Gated::open(0);
+        let manager = manager(dir.path(), backend.clone());
+        let queue = JobQueue::with_circuit(1, 1, None, &CLOSED);
+
+        // one job runs, one waits in the queue
+        let running = queue
+            .submit(job(dir.path(), "running.png", &manager))
+            .await
+            .unwrap();
+        wait_until(|| status(running) == Some(JobStatus::Running)).await;
+        let waiting = queue
+            .submit(job(dir.path(), "waiting.png", &manager))
+            .await
+            .unwrap();
+
+        let blocked = {
+            let queue = queue.clone();
+            let job = job(dir.path(), "blocked.png", &manager);
+            tokio::spawn(async move { queue.submit(job).await })
+        };
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!blocked.is_finished());
+        assert_eq!(status(waiting), Some(JobStatus::Queued));
+
+        backend.gate.add_permits(1);
+        let blocked = tokio::time::timeout(Duration::from_secs(10), blocked)
+            .await
+            .expect("submission stayed blocked")
+            .unwrap()
+            .unwrap();
+        wait_until(|| {
+            [running, waiting, blocked]
+                .iter()
+                .all(|id| matches!(status(*id), Some(JobStatus::Done { .. })))
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn rejects_files_that_are_already_queued() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = Gated::open(0);
+        let manager = manager(dir.path(), backend.clone());
+        let queue = JobQueue::with_circuit(1, 10, None, &CLOSED);
+
+        let first = queue
+            .submit(job(dir.path(), "twice.png", &manager))
+            .await
+            .unwrap();
+        assert_eq!(
+            queue.submit(job(dir.path(), "twice.png", &manager)).await,
+            None
+        );
+
+        backend.gate.add_permits(1);
+        wait_until(|| status(first).is_some_and(|status| status.is_finished())).await;
+        // once finished, the same path can be queued again
+        let again = job(dir.path(), "twice.png", &manager);
+        assert!(queue.submit(again).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn reports_each_status_on_the_board() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = Gated::open(0);
+        let manager = manager(dir.path(), backend.clone());
+        let queue = JobQueue::with_circuit(1, 10, None, &CLOSED);
+
+        let first = queue
+            .submit(job(dir.path(), "first.png", &manager))
+            .await
+            .unwrap();
+        let second = queue
+            .submit(job(dir.path(), "second.png", &manager))
+            .await
+            .unwrap();
+        let missing = queue
+            .submit(Job {
+                path: dir.path().join("missing.png"),
+                context: NamingContext::default(),
+                manager: manager.clone(),
+                screenshot: false,
+            })
+            .await
+            .unwrap();
+
+        wait_until(|| status(first) == Some(JobStatus::Running)).await;
+        assert_eq!(status(second), Some(JobStatus::Queued));
+        assert_eq!(status(missing), Some(JobStatus::Queued));
+
+        backend.gate.add_permits(3);
+        wait_until(|| status(missing).is_some_and(|status| status.is_finished())).await;
+        for id in [first, second] {
+            let Some(JobStatus::Done { new_path }) = status(id) else {
+                panic!("job {} did not finish", id);
+            };
+            assert!(new_path.exists());
+        }
+        assert!(matches!(status(missing), Some(JobStatus::Failed { .. })));
+
+        let info = job_info(first).unwrap();
+        assert!(info.finished_at.is_some_and(|at| at >= info.queued_at));
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::watcher::error::GogglesError;
+
+/// Consecutive transient failures that open `SERVER_CIRCUIT`.
+const FAILURE_THRESHOLD: u32 = 5;
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Tracks the health of the naming server for every backend and the queue.
+pub static SERVER_CIRCUIT: CircuitBreaker = CircuitBreaker::new(FAILURE_THRESHOLD, COOLDOWN);
+
+/// Failure of a single attempt, tells `retry` whether another one can help.
+#[derive(Debug)]
+pub enum AttemptError {
+    /// Timeouts, refused or dropped connections, 5xx and 429 responses.
+    Transient(anyhow::Error),
+    /// Anything a retry won't fix, like 4xx responses or unreadable files.
+    Permanent(anyhow::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with full jitter before retry number `retry`.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let random = RandomState::new().build_hasher().finish();
+        Duration::from_millis(random % (ceiling.as_millis() as u64 + 1))
+    }
+}
+
+/// How often callers check again while the half-open probe is running.
+const PROBE_POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+enum CircuitState {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// The cooldown is over and one request is let through to test the
+    /// server, `probing` while it runs.
+    HalfOpen {
+        probing: bool,
+    },
+}
+
+/// Stops calling a server that keeps failing. After `cooldown` a single
+/// probe request is let through, its success closes the circuit and its
+/// failure re-opens it.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub const fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Time until requests are allowed again, `None` when the next one
+    /// may go out.
+    pub fn remaining(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        match *state {
+            CircuitState::Closed { .. } | CircuitState::HalfOpen { probing: false } => None,
+            CircuitState::HalfOpen { probing: true } => Some(PROBE_POLL),
+            CircuitState::Open { until } => until
+                .checked_duration_since(Instant::now())
+                .filter(|remaining| !remaining.is_zero()),
+        }
+    }
+
+    /// Claims the right to send a request. Once the cooldown is over only
+    /// the first caller gets through, as the probe.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::HalfOpen { probing: true } => Err(PROBE_POLL),
+            CircuitState::HalfOpen { probing: false } => {
+                *state = CircuitState::HalfOpen { probing: true };
+                Ok(())
+            }
+            CircuitState::Open { until } => match until.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => Err(remaining),
+                _ => {
+                    info!("Cooldown over, probing the server");
+                    *state = CircuitState::HalfOpen { probing: true };
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, CircuitState::Closed { .. }) {
+            info!("Server is reachable again, closing circuit");
+        }
+        *state = CircuitState::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            CircuitState::Closed { failures } => failures + 1,
+            // the probe failed, back to waiting
+            _ => self.threshold,
+        };
+        if failures >= self.threshold {
+            warn!(
+                "Server failed {} times in a row, pausing requests for {:?}",
+                failures, self.cooldown
+            );
+            *state = CircuitState::Open {
+                until: Instant::now() + self.cooldown,
+            };
+        } else {
+            *state = CircuitState::Closed { failures };
+        }
+    }
+
+    /// Ends a request that says nothing about the server's health, so
+    /// another caller may probe.
+    pub fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let CircuitState::HalfOpen { probing: true } = *state {
+            *state = CircuitState::HalfOpen { probing: false };
+        }
+    }
+
+    /// Sleeps until a request may go out again.
+    pub async fn wait_until_closed(&self) {
+        while let Some(remaining) = self.remaining() {
+            tokio::time::sleep(remaining).await;
+        }
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails permanently or runs out of
+/// retries, backing off between transient failures.
+pub async fn retry<T, F, Fut>(
+    policy: &RetryPolicy,
+    circuit: &CircuitBreaker,
+    mut attempt: F,
+) -> Result<T, anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AttemptError>>,
+{
+    let mut retries = 0;
+    let mut last_error = None;
+    loop {
+        if let Err(remaining) = circuit.try_acquire() {
+            // once this request failed itself, waiting for the server and
+            // trying again would only repeat that, so report the failure
+            return Err(last_error.unwrap_or_else(|| {
+                GogglesError::ServerUnavailable {
+                    retry_in_secs: remaining.as_secs(),
+                }
+                .into()
+            }));
+        }
+
+        match attempt().await {
+            Ok(value) => {
+                circuit.record_success();
+                return Ok(value);
+            }
+            Err(AttemptError::Permanent(e)) => {
+                circuit.release();
+                return Err(e);
+            }
+            Err(AttemptError::Transient(e)) => {
+                circuit.record_failure();
+                if retries >= policy.max_retries {
+                    return Err(e);
+                }
+                let delay = policy.delay(retries);
+                retries += 1;
+                warn!(
+                    "Request failed ({}), retry {} of {} in {:?}",
+                    e, retries, policy.max_retries, delay
+                );
+                last_error = Some(e);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    #[test]
+    fn delay_stays_under_the_growing_ceiling() {
+        let policy = RetryPolicy::default();
+        for retry in 0..10 {
+            let ceiling = policy
+                .base_delay
+                .saturating_mul(2u32.pow(retry))
+                .min(policy.max_delay);
+            for _ in 0..50 {
+                assert!(policy.delay(retry) <= ceiling);
+            }
+        }
+    }
+
+    #[test]
+    fn delay_is_jittered() {
+        let policy = RetryPolicy::default();
+        let delays: std::collections::HashSet<_> = (0..50).map(|_| policy.delay(5)).collect();
+        assert!(delays.len() > 1);
+    }
+
+    #[test]
+    fn breaker_opens_after_the_threshold() {
+        let circuit = CircuitBreaker::new(3, Duration::from_secs(60));
+        circuit.record_failure();
+        circuit.record_failure();
+        assert!(circuit.try_acquire().is_ok());
+        circuit.record_failure();
+        assert!(circuit.remaining().is_some());
+        assert!(circuit.try_acquire().is_err());
+    }
+
+    #[test]
+    fn success_resets_the_failure_count() {
+        let circuit = CircuitBreaker::new(2, Duration::from_secs(60));
+        circuit.record_failure();
+        circuit.record_success();
+        circuit.record_failure();
+        assert!(circuit.remaining().is_none());
+    }
+
+    #[test]
+    fn half_open_lets_one_probe_through() {
+        let circuit = CircuitBreaker::new(1, Duration::from_millis(10));
+        circuit.record_failure();
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(circuit.remaining().is_none());
+        assert!(circuit.try_acquire().is_ok());
+        assert!(circuit.try_acquire().is_err());
+        assert_eq!(circuit.remaining(), Some(PROBE_POLL));
+
+        circuit.record_success();
+        assert!(circuit.try_acquire().is_ok());
+        assert!(circuit.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn failed_probe_reopens_the_circuit() {
+        let circuit = CircuitBreaker::new(3, Duration::from_millis(10));
+        for _ in 0..3 {
+            circuit.record_failure();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(circuit.try_acquire().is_ok());
+        circuit.record_failure();
+        assert!(circuit.try_acquire().is_err());
+    }
+
+    #[test]
+    fn released_probe_lets_the_next_caller_probe() {
+        let circuit = CircuitBreaker::new(1, Duration::from_millis(10));
+        circuit.record_failure();
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(circuit.try_acquire().is_ok());
+        circuit.release();
+        assert!(circuit.try_acquire().is_ok());
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let circuit = CircuitBreaker::new(10, Duration::from_secs(60));
+        let calls = AtomicU32::new(0);
+        let result = retry(&fast_policy(3), &circuit, || async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(AttemptError::Transient(anyhow::anyhow!("503")))
+            } else {
+                Ok("name")
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), "name");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let circuit = CircuitBreaker::new(10, Duration::from_secs(60));
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = retry(&fast_policy(2), &circuit, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(AttemptError::Transient(anyhow::anyhow!("503")))
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_not_retried_or_counted() {
+        let circuit = CircuitBreaker::new(1, Duration::from_secs(60));
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = retry(&fast_policy(3), &circuit, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(AttemptError::Permanent(anyhow::anyhow!("400")))
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(circuit.remaining().is_none());
+    }
+
+    #[tokio::test]
+    async fn circuit_opened_mid_retry_returns_the_last_error() {
+        let circuit = CircuitBreaker::new(2, Duration::from_secs(60));
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = retry(&fast_policy(5), &circuit, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(AttemptError::Transient(anyhow::anyhow!("500")))
+        })
+        .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "500");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(circuit.remaining().is_some());
+    }
+
+    #[tokio::test]
+    async fn open_circuit_fails_fast() {
+        let circuit = CircuitBreaker::new(1, Duration::from_secs(60));
+        circuit.record_failure();
+        let result: Result<(), _> = retry(&fast_policy(3), &circuit, || async {
+            panic!("no request while the circuit is open")
+        })
+        .await;
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<GogglesError>(),
+            Some(GogglesError::ServerUnavailable { .. })
+        ));
+    }
+}
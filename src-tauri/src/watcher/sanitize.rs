@@ -0,0 +1,101 @@
+use crate::watcher::error::GogglesError;
+
+/// Longest stem we produce in bytes, leaving room for collision suffixes
+/// and the extension within the usual 255 byte filename limit.
+pub const MAX_FILENAME_LEN: usize = 100;
+
+const RESERVED_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Turns a generated name into a safe file stem (no extension).
+///
+/// Anything outside alphanumerics, `-` and `_` becomes a hyphen, runs of
+/// hyphens collapse, and the result is cut to `MAX_FILENAME_LEN` bytes.
+/// Separators and dots never survive, so the stem can't leave its folder.
+pub fn sanitize_filename(raw: &str) -> Result<String, anyhow::Error> {
+    let raw = raw.trim();
+
+    let mut name = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        let c = if c.is_alphanumeric() || c == '_' {
+            c
+        } else {
+            '-'
+        };
+        if c == '-' && (name.is_empty() || name.ends_with('-')) {
+            continue;
+        }
+        name.push(c);
+    }
+
+    // non-ASCII letters take several bytes, cut on a char boundary
+    let mut end = name.len().min(MAX_FILENAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name.truncate(end);
+    let name = name.trim_matches(['-', '_']).to_string();
+
+    if name.is_empty() {
+        return Err(GogglesError::InvalidName(format!(
+            "Generated filename has no usable characters: {:?}",
+            raw
+        ))
+        .into());
+    }
+
+    if RESERVED_NAMES.contains(&name.to_lowercase().as_str()) {
+        return Ok(format!("{}-file", name));
+    }
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn accepts_dotted_names() {
+        assert_eq!(sanitize_filename("Loading...").unwrap(), "Loading");
+        assert_eq!(sanitize_filename("../../etc/passwd").unwrap(), "etc-passwd");
+    }
+
+    #[test]
+    fn cuts_multibyte_names_by_bytes() {
+        let name = sanitize_filename(&"截图".repeat(100)).unwrap();
+        assert!(name.len() <= MAX_FILENAME_LEN);
+        assert!(name.chars().all(|c| c == '截' || c == '图'));
+    }
+
+    proptest! {
+        #[test]
+        fn output_is_a_safe_stem(raw in "\\PC*") {
+            if let Ok(name) = sanitize_filename(&raw) {
+                prop_assert!(!name.is_empty());
+                prop_assert!(name.len() <= MAX_FILENAME_LEN + "-file".len());
+                prop_assert!(name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_'));
+                prop_assert!(!name.starts_with(['-', '_']) && !name.ends_with(['-', '_']));
+                prop_assert!(!name.contains("--"));
+            }
+        }
+
+        #[test]
+        fn sanitizing_twice_changes_nothing(raw in "\\PC*") {
+            if let Ok(name) = sanitize_filename(&raw) {
+                prop_assert_eq!(sanitize_filename(&name).unwrap(), name);
+            }
+        }
+
+        #[test]
+        fn path_like_names_stay_in_the_folder(parts in prop::collection::vec("[a-z.]{1,8}", 1..5)) {
+            let raw = parts.join("/");
+            if let Ok(name) = sanitize_filename(&raw) {
+                prop_assert!(!name.contains('/') && !name.contains('.'));
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+
+use crate::watcher::naming::NamingContext;
+#[cfg(feature = "gui")]
+use crate::watcher::sanitize::sanitize_filename;
+
+pub const DEFAULT_TEMPLATE: &str = "{ai}";
+
+/// Stand-in for `{ai}` in previews, so previewing costs no credits.
+#[cfg(feature = "gui")]
+const PREVIEW_AI_NAME: &str = "ai-suggested-name";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Ai,
+    Date,
+    Time,
+    DateTime,
+    App,
+    Original,
+    Counter,
+    Width,
+    Height,
+    Dims,
+}
+
+impl Placeholder {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "ai" => Some(Self::Ai),
+            "date" => Some(Self::Date),
+            "time" => Some(Self::Time),
+            "datetime" => Some(Self::DateTime),
+            "app" => Some(Self::App),
+            "original" => Some(Self::Original),
+            "counter" => Some(Self::Counter),
+            "width" => Some(Self::Width),
+            "height" => Some(Self::Height),
+            "dims" => Some(Self::Dims),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// Everything a template can refer to for one file.
+#[derive(Debug, Clone)]
+pub struct TemplateValues {
+    pub ai: Option<String>,
+    pub timestamp: DateTime<Local>,
+    pub app: Option<String>,
+    pub original: String,
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl TemplateValues {
+    pub fn collect(path: &Path, context: &NamingContext, ai: Option<String>) -> Self {
+        let timestamp = fs::metadata(path)
+            .and_then(|metadata| metadata.created().or_else(|_| metadata.modified()))
+            .map(DateTime::<Local>::from)
+            .unwrap_or_else(|_| Local::now());
+
+        Self {
+            ai,
+            timestamp,
+            app: context
+                .frontmost
+                .as_ref()
+                .map(|frontmost| frontmost.app_name.clone()),
+            original: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            // only reads the header, cheap even for large files
+            dimensions: image::image_dimensions(path).ok(),
+        }
+    }
+}
+
+/// A filename pattern such as `{date}-{app}-{ai}`, rendered to a file stem.
+///
+/// Supported placeholders: `{ai}`, `{date}`, `{time}`, `{datetime}`, `{app}`,
+/// `{original}`, `{counter}`, `{width}`, `{height}` and `{dims}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    segments: Vec<Segment>,
+}
+
+impl NameTemplate {
+    pub fn parse(template: &str) -> Result<Self, anyhow::Error> {
+        if template.trim().is_empty() {
+            return Err(anyhow::anyhow!("Template is empty"));
+        }
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(anyhow::anyhow!(
+                                    "Unclosed placeholder {{{} in template",
+                                    name
+                                ))
+                            }
+                        }
+                    }
+                    let placeholder = Placeholder::parse(&name).ok_or_else(|| {
+                        anyhow::anyhow!("Unknown placeholder {{{}}} in template", name)
+                    })?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(placeholder));
+                }
+                '}' => return Err(anyhow::anyhow!("Unmatched }} in template")),
+                '/' | '\\' => {
+                    return Err(anyhow::anyhow!("Template must not contain path separators"))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    fn uses(&self, placeholder: Placeholder) -> bool {
+        self.segments.contains(&Segment::Placeholder(placeholder))
+    }
+
+    /// Whether rendering needs a name from the naming backend.
+    pub fn needs_ai(&self) -> bool {
+        self.uses(Placeholder::Ai)
+    }
+
+    pub fn has_counter(&self) -> bool {
+        self.uses(Placeholder::Counter)
+    }
+
+    /// Renders the template. Missing values render as empty strings, the
+    /// sanitizer collapses the leftover separators.
+    pub fn render(&self, values: &TemplateValues, counter: u32) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => out += literal,
+                Segment::Placeholder(placeholder) => match placeholder {
+                    Placeholder::Ai => out += values.ai.as_deref().unwrap_or_default(),
+                    Placeholder::Date => {
+                        out += &values.timestamp.format("%Y-%m-%d").to_string();
+                    }
+                    Placeholder::Time => {
+                        out += &values.timestamp.format("%H%M%S").to_string();
+                    }
+                    Placeholder::DateTime => {
+                        out += &values.timestamp.format("%Y-%m-%dT%H%M%S").to_string();
+                    }
+                    Placeholder::App => out += values.app.as_deref().unwrap_or_default(),
+                    Placeholder::Original => out += &values.original,
+                    Placeholder::Counter => out += &counter.to_string(),
+                    Placeholder::Width => {
+                        if let Some((width, _)) = values.dimensions {
+                            out += &width.to_string();
+                        }
+                    }
+                    Placeholder::Height => {
+                        if let Some((_, height)) = values.dimensions {
+                            out += &height.to_string();
+                        }
+                    }
+                    Placeholder::Dims => {
+                        if let Some((width, height)) = values.dimensions {
+                            out += &format!("{}x{}", width, height);
+                        }
+                    }
+                },
+            }
+        }
+        out
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE).unwrap()
+    }
+}
+
+/// Renders `template` against `path` with a sample AI name and returns the
+/// resulting filename, or the validation error.
+#[cfg(feature = "gui")]
+pub fn preview_template(template: &str, path: &Path) -> Result<String, anyhow::Error> {
+    let template = NameTemplate::parse(template)?;
+    let values = TemplateValues::collect(
+        path,
+        &NamingContext::default(),
+        Some(PREVIEW_AI_NAME.to_string()),
+    );
+    let stem = sanitize_filename(&template.render(&values, 1))?;
+
+    match path.extension() {
+        Some(ext) => Ok(format!("{}.{}", stem, ext.to_string_lossy())),
+        None => Ok(stem),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher::sanitize::sanitize_filename;
+    use chrono::TimeZone;
+
+    fn values() -> TemplateValues {
+        TemplateValues {
+            ai: Some("cat-on-keyboard".to_string()),
+            timestamp: Local.with_ymd_and_hms(2026, 3, 4, 5, 6, 7).unwrap(),
+            app: Some("Safari".to_string()),
+            original: "Screenshot 2026".to_string(),
+            dimensions: Some((1440, 900)),
+        }
+    }
+
+    fn render(template: &str, values: &TemplateValues) -> String {
+        NameTemplate::parse(template).unwrap().render(values, 3)
+    }
+
+    #[test]
+    fn renders_every_placeholder() {
+        let values = values();
+        assert_eq!(render("{ai}", &values), "cat-on-keyboard");
+        assert_eq!(render("{date}_{time}", &values), "2026-03-04_050607");
+        assert_eq!(render("{datetime}", &values), "2026-03-04T050607");
+        assert_eq!(
+            render("{app}-{original}", &values),
+            "Safari-Screenshot 2026"
+        );
+        assert_eq!(
+            render("{width}x{height} {dims}", &values),
+            "1440x900 1440x900"
+        );
+        assert_eq!(render("shot-{counter}", &values), "shot-3");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for (template, error) in [
+            ("", "Template is empty"),
+            ("  ", "Template is empty"),
+            ("{nope}", "Unknown placeholder {nope} in template"),
+            ("{AI}", "Unknown placeholder {AI} in template"),
+            ("{ai", "Unclosed placeholder {ai in template"),
+            ("{date}-{", "Unclosed placeholder { in template"),
+            ("ai}", "Unmatched } in template"),
+            ("{date}/{ai}", "Template must not contain path separators"),
+            ("{date}\\{ai}", "Template must not contain path separators"),
+        ] {
+            let message = NameTemplate::parse(template).unwrap_err().to_string();
+            assert_eq!(message, error, "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn knows_what_it_needs() {
+        let template = NameTemplate::parse("{date}-{ai}").unwrap();
+        assert!(template.needs_ai());
+        assert!(!template.has_counter());
+
+        let template = NameTemplate::parse("{app}-{counter}").unwrap();
+        assert!(!template.needs_ai());
+        assert!(template.has_counter());
+        assert_eq!(
+            NameTemplate::default(),
+            NameTemplate::parse("{ai}").unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_values_collapse_after_sanitizing() {
+        let values = TemplateValues {
+            ai: None,
+            app: None,
+            dimensions: None,
+            ..values()
+        };
+        let rendered = render("{app}--{ai}-{date}-{dims}", &values);
+        assert_eq!(rendered, "---2026-03-04-");
+        assert_eq!(sanitize_filename(&rendered).unwrap(), "2026-03-04");
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn previews_with_a_sample_ai_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Screenshot 1.png");
+        image::RgbImage::new(64, 32).save(&path).unwrap();
+
+        assert_eq!(
+            preview_template("{ai}-{dims}", &path).unwrap(),
+            "ai-suggested-name-64x32.png"
+        );
+        assert_eq!(
+            preview_template("{original} {counter}", &path).unwrap(),
+            "Screenshot-1-1.png"
+        );
+        assert_eq!(
+            preview_template("{ai}", &dir.path().join("notes")).unwrap(),
+            "ai-suggested-name"
+        );
+        assert!(preview_template("{nope}", &path).is_err());
+        // nothing usable left once the empty values are gone
+        assert!(preview_template("{app}", &path).is_err());
+    }
+}
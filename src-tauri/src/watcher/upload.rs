@@ -0,0 +1,288 @@
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageReader, Rgb, RgbImage};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::watcher::config::GogglesConfig;
+use crate::watcher::error::GogglesError;
+use crate::watcher::privacy::{redact, RedactRegion};
+
+/// Qualities tried in order until a JPEG fits the byte budget.
+const JPEG_QUALITIES: [u8; 4] = [85, 70, 55, 40];
+/// Images are never scaled below this to meet the budget, and
+/// `upload_max_dimension` can't be set lower.
+pub const MIN_DIMENSION: u32 = 256;
+
+/// Encoding of images sent to the naming server.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadFormat {
+    #[default]
+    Jpeg,
+    /// Lossless WebP, falling back to JPEG when it doesn't fit the budget.
+    Webp,
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    pub max_dimension: u32,
+    pub max_bytes: usize,
+    pub format: UploadFormat,
+    pub redact_regions: Vec<RedactRegion>,
+}
+
+impl UploadOptions {
+    pub fn from_config(config: &GogglesConfig) -> Self {
+        Self {
+            max_dimension: config.upload_max_dimension,
+            max_bytes: config.upload_max_bytes,
+            format: config.upload_format,
+            redact_regions: config.redact_regions.clone(),
+        }
+    }
+}
+
+/// An image ready to be sent, re-encoded without any of the original
+/// metadata.
+#[derive(Debug, Clone)]
+pub struct PreparedUpload {
+    pub bytes: Vec<u8>,
+    pub mime_type: &'static str,
+    pub file_name: String,
+}
+
+/// Covers the redact regions of `path`, scales it down to `max_dimension`
+/// and re-encodes it, shrinking further until it fits `max_bytes`. Files
+/// the image decoder can't read fail with `NotAnImage` and are never sent,
+/// the server only accepts images anyway.
+pub fn prepare_upload(
+    path: &Path,
+    options: &UploadOptions,
+) -> Result<PreparedUpload, anyhow::Error> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "image".to_string());
+
+    let mut image = match ImageReader::open(path)?.with_guessed_format()?.decode() {
+        Ok(image) => image,
+        Err(e) => {
+            info!("Can't decode {:?} ({}), not uploading it", path, e);
+            return Err(GogglesError::NotAnImage(path.to_path_buf()).into());
+        }
+    };
+    redact(&mut image, &options.redact_regions);
+
+    // shrinking starts from the image's own size, a larger limit changes
+    // nothing. The config can't go below the minimum, options built by
+    // hand can
+    let longest = image.width().max(image.height());
+    let mut dimension = options.max_dimension.min(longest).max(MIN_DIMENSION);
+    loop {
+        let scaled = if image.width().max(image.height()) > dimension {
+            image.resize(dimension, dimension, FilterType::Triangle)
+        } else {
+            image.clone()
+        };
+
+        let (bytes, mime_type, extension) = encode(&scaled, options)?;
+        if bytes.len() <= options.max_bytes || dimension <= MIN_DIMENSION {
+            info!(
+                "Prepared upload of {:?}: {}x{}, {} bytes",
+                path,
+                scaled.width(),
+                scaled.height(),
+                bytes.len()
+            );
+            return Ok(PreparedUpload {
+                bytes,
+                mime_type,
+                file_name: format!("{}.{}", stem, extension),
+            });
+        }
+
+        dimension = (dimension - dimension / 4).max(MIN_DIMENSION);
+    }
+}
+
+/// Smallest encoding that fits the budget, or the smallest one tried.
+fn encode(
+    image: &DynamicImage,
+    options: &UploadOptions,
+) -> Result<(Vec<u8>, &'static str, &'static str), anyhow::Error> {
+    if options.format == UploadFormat::Webp {
+        let mut bytes = Vec::new();
+        image
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut bytes))?;
+        if bytes.len() <= options.max_bytes {
+            return Ok((bytes, "image/webp", "webp"));
+        }
+    }
+
+    let rgb = flatten(image);
+    let mut bytes = Vec::new();
+    for quality in JPEG_QUALITIES {
+        bytes.clear();
+        rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))?;
+        if bytes.len() <= options.max_bytes {
+            break;
+        }
+    }
+    Ok((bytes, "image/jpeg", "jpg"))
+}
+
+/// Drops the alpha channel by blending onto white, JPEG has no alpha.
+fn flatten(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = pixel[3] as u16;
+        let blend = |c: u8| ((c as u16 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        Rgb([blend(pixel[0]), blend(pixel[1]), blend(pixel[2])])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn options(max_dimension: u32, max_bytes: usize) -> UploadOptions {
+        UploadOptions {
+            max_dimension,
+            max_bytes,
+            format: UploadFormat::Jpeg,
+            redact_regions: vec![],
+        }
+    }
+
+    /// Noisy enough that the encoders can't shrink it to nothing.
+    fn sample(dir: &Path, name: &str, width: u32, height: u32, alpha: u8) -> PathBuf {
+        let path = dir.join(name);
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([
+                (x % 251) as u8,
+                (y % 241) as u8,
+                ((x ^ y) % 256) as u8,
+                alpha,
+            ])
+        })
+        .save(&path)
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn scales_the_longest_side_down() {
+        let dir = tempfile::tempdir().unwrap();
+        for (width, height) in [(2000, 800), (600, 1600)] {
+            let path = sample(dir.path(), "shot.png", width, height, 255);
+            let upload = prepare_upload(&path, &options(1024, 10 * 1024 * 1024)).unwrap();
+            let decoded = image::load_from_memory(&upload.bytes).unwrap();
+
+            assert_eq!(decoded.width().max(decoded.height()), 1024);
+            assert_eq!(upload.mime_type, "image/jpeg");
+            assert_eq!(upload.file_name, "shot.jpg");
+        }
+    }
+
+    #[test]
+    fn fits_the_byte_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = sample(dir.path(), "shot.png", 1600, 1000, 255);
+        let upload = prepare_upload(&path, &options(1600, 40 * 1024)).unwrap();
+
+        assert!(upload.bytes.len() <= 40 * 1024);
+    }
+
+    #[test]
+    fn stops_at_the_minimum_size_with_a_tiny_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = sample(dir.path(), "shot.png", 900, 600, 255);
+        let upload = prepare_upload(&path, &options(u32::MAX, 1)).unwrap();
+        let decoded = image::load_from_memory(&upload.bytes).unwrap();
+
+        // the smallest encoding tried, sent anyway
+        assert_eq!(decoded.width().max(decoded.height()), MIN_DIMENSION);
+    }
+
+    #[test]
+    fn strips_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = sample(dir.path(), "shot.png", 64, 64, 255);
+        let mut jpeg = Vec::new();
+        image::open(&source)
+            .unwrap()
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, 90))
+            .unwrap();
+
+        // an APP1 segment with EXIF right after the start of image marker
+        let payload = b"Exif\0\0MM\0\x2a\0\0\0\x08secret-gps";
+        let length = (payload.len() + 2) as u16;
+        let mut with_exif = jpeg[..2].to_vec();
+        with_exif.extend_from_slice(&[0xFF, 0xE1]);
+        with_exif.extend_from_slice(&length.to_be_bytes());
+        with_exif.extend_from_slice(payload);
+        with_exif.extend_from_slice(&jpeg[2..]);
+        let path = dir.path().join("exif.jpg");
+        fs::write(&path, &with_exif).unwrap();
+
+        let upload = prepare_upload(&path, &options(1024, 1024 * 1024)).unwrap();
+
+        let contains = |needle: &[u8]| upload.bytes.windows(needle.len()).any(|w| w == needle);
+        assert!(!contains(b"Exif"));
+        assert!(!contains(b"secret-gps"));
+    }
+
+    #[test]
+    fn flattens_alpha_onto_white() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = sample(dir.path(), "clear.png", 64, 64, 0);
+        let upload = prepare_upload(&path, &options(1024, 1024 * 1024)).unwrap();
+        let decoded = image::load_from_memory(&upload.bytes).unwrap();
+
+        assert!(!decoded.color().has_alpha());
+        let pixel = decoded.to_rgb8().get_pixel(32, 32).0;
+        assert!(pixel.iter().all(|&c| c > 245), "{:?}", pixel);
+    }
+
+    #[test]
+    fn uses_webp_when_it_fits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = sample(dir.path(), "shot.png", 64, 64, 128);
+        let upload = prepare_upload(
+            &path,
+            &UploadOptions {
+                format: UploadFormat::Webp,
+                ..options(1024, 1024 * 1024)
+            },
+        )
+        .unwrap();
+
+        assert_eq!(upload.mime_type, "image/webp");
+        assert!(image::load_from_memory(&upload.bytes).is_ok());
+    }
+
+    #[test]
+    fn never_sends_files_that_are_not_images() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["report.pdf", "broken.png"] {
+            let path = dir.path().join(name);
+            fs::write(&path, b"%PDF-1.7 not an image").unwrap();
+
+            let error = prepare_upload(&path, &options(1024, 1024)).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<GogglesError>(),
+                Some(GogglesError::NotAnImage(_))
+            ));
+        }
+    }
+}
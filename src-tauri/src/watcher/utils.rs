@@ -1,22 +1,22 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Error;
 use log::error;
 
 fn get_clean_path(raw: String, home: PathBuf) -> Result<PathBuf, Error> {
-    if raw.starts_with("~/") {
-        return Ok(home.join(raw[2..].to_string()));
+    if let Some(rest) = raw.strip_prefix("~/") {
+        return Ok(home.join(rest));
     }
 
     let raw_path = PathBuf::from(raw.clone());
     // if raw_path has home path, then return raw_path
     if raw_path.starts_with(&home) {
-        return Ok(raw_path);
+        Ok(raw_path)
     } else {
-        return Err(anyhow::anyhow!(
+        Err(anyhow::anyhow!(
             "Raw path {} is not understanding",
             raw_path.display()
-        ));
+        ))
     }
 }
 
@@ -52,3 +52,11 @@ pub fn get_screenshot_dir() -> PathBuf {
         }
     }
 }
+
+/// Expands a leading `~/` to the home directory.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
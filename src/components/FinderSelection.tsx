@@ -2,11 +2,25 @@ import { useQuery } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/core";
 import { ScanTextIcon } from "./ui/scan-text";
 import { useState } from "react";
+import { errorMessage, isGogglesError } from "../lib/errors";
 
 interface FinderSelectionProps {
   className?: string;
 }
 
+interface FileError {
+  message: string;
+  /** Extra explanation sent by the naming server */
+  details?: string;
+}
+
+function toFileError(error: unknown): FileError {
+  if (isGogglesError(error)) {
+    return { message: error.message, details: error.details };
+  }
+  return { message: errorMessage(error) };
+}
+
 export default function FinderSelection({
   className = "",
 }: FinderSelectionProps) {
@@ -14,7 +28,9 @@ export default function FinderSelection({
     new Set()
   );
   const [processedFiles, setProcessedFiles] = useState<Set<string>>(new Set());
-  const [errorFiles, setErrorFiles] = useState<Set<string>>(new Set());
+  const [errorFiles, setErrorFiles] = useState<Map<string, FileError>>(
+    new Map()
+  );
 
   const {
     data: selectedPaths = [],
@@ -35,17 +51,17 @@ export default function FinderSelection({
   const handleProcessImage = async (filePath: string) => {
     setProcessingFiles((prev) => new Set(prev).add(filePath));
     setErrorFiles((prev) => {
-      const newSet = new Set(prev);
-      newSet.delete(filePath);
-      return newSet;
+      const newMap = new Map(prev);
+      newMap.delete(filePath);
+      return newMap;
     });
 
     try {
       await invoke<string>("process_image_with_ai", { filePath });
       setProcessedFiles((prev) => new Set(prev).add(filePath));
     } catch (error) {
-      console.error("Failed to process image:", error);
-      setErrorFiles((prev) => new Set(prev).add(filePath));
+      console.error("Failed to process image:", errorMessage(error));
+      setErrorFiles((prev) => new Map(prev).set(filePath, toFileError(error)));
     } finally {
       setProcessingFiles((prev) => {
         const newSet = new Set(prev);
@@ -93,6 +109,7 @@ export default function FinderSelection({
             {selectedPaths.map((path, index) => {
               const fileName = path.split("/").pop() || path;
               const filePath = path.replace(fileName, "").slice(0, -1);
+              const fileError = errorFiles.get(path);
 
               return (
                 <div
@@ -165,6 +182,16 @@ export default function FinderSelection({
                       )}
                     </button>
                   </div>
+                  {fileError && (
+                    <div className="mt-1.5 text-xs" role="alert">
+                      <p className="font-semibold text-black">
+                        {fileError.message}
+                      </p>
+                      {fileError.details && (
+                        <p className="text-gray-600">{fileError.details}</p>
+                      )}
+                    </div>
+                  )}
                 </div>
               );
             })}
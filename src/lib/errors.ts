@@ -0,0 +1,44 @@
+// Mirrors `GogglesError` in src-tauri/src/watcher/error.rs
+export type GogglesErrorKind =
+  | "network"
+  | "server"
+  | "insufficient_credits"
+  | "server_unavailable"
+  | "invalid_response"
+  | "invalid_name"
+  | "already_exists"
+  | "not_a_screenshot"
+  | "not_an_image"
+  | "already_renamed"
+  | "private"
+  | "io"
+  | "config"
+  | "other";
+
+export interface GogglesError {
+  kind: GogglesErrorKind;
+  message: string;
+  /** Extra explanation sent by the naming server */
+  details?: string;
+  status?: number;
+  retry_in_secs?: number;
+  path?: string;
+  /** Privacy rule that kept the file on the device */
+  rule?: string;
+}
+
+export function isGogglesError(error: unknown): error is GogglesError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    "kind" in error &&
+    "message" in error
+  );
+}
+
+export function errorMessage(error: unknown): string {
+  if (isGogglesError(error)) {
+    return error.details ? `${error.message}: ${error.details}` : error.message;
+  }
+  return error instanceof Error ? error.message : String(error);
+}